@@ -0,0 +1,75 @@
+use std::fmt;
+
+use rand::{rngs::OsRng, Rng, RngCore};
+
+/// Ports below this are reserved, so source ports are picked above it (RFC 6056 2.1).
+const MIN_SOURCE_PORT: u16 = 1024;
+
+/// Where the random query ids and source ports come from.
+///
+/// An off-path attacker has to guess both to spoof a response, so they must
+/// be unpredictable. Tests can plug in a deterministic implementation.
+pub trait Entropy: fmt::Debug + Send + Sync {
+    /// A uniformly random 16 bit value.
+    fn next_u16(&self) -> u16;
+
+    /// The id of the next query.
+    fn query_id(&self) -> u16 {
+        self.next_u16()
+    }
+
+    /// The local port the next query is sent from. This maps `next_u16` onto
+    /// the range, so that deterministic sources pick known ports, but is not
+    /// quite uniform: sources drawing from an RNG should override it.
+    fn source_port(&self) -> u16 {
+        MIN_SOURCE_PORT + self.next_u16() % (u16::MAX - MIN_SOURCE_PORT + 1)
+    }
+}
+
+/// The cryptographically secure random number generator of the operating system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsEntropy;
+
+impl Entropy for OsEntropy {
+    fn next_u16(&self) -> u16 {
+        OsRng.next_u32() as u16
+    }
+
+    fn source_port(&self) -> u16 {
+        OsRng.gen_range(MIN_SOURCE_PORT..=u16::MAX)
+    }
+}
+
+/// Always the same value, for tests.
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct Fixed(pub(crate) u16);
+
+#[cfg(test)]
+impl Entropy for Fixed {
+    fn next_u16(&self) -> u16 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests_entropy {
+    use super::*;
+
+    #[test]
+    fn test_source_port_range() {
+        assert_eq!(Fixed(0).source_port(), 1024);
+        assert_eq!(Fixed(u16::MAX).source_port(), 1023 + 1024);
+        assert_eq!(Fixed(u16::MAX - 1024).source_port(), u16::MAX);
+        assert_eq!(Fixed(7).query_id(), 7);
+    }
+
+    #[test]
+    fn test_os_entropy_varies() {
+        let ids: Vec<u16> = (0..8).map(|_| OsEntropy.query_id()).collect();
+        assert!(ids.iter().any(|id| *id != ids[0]));
+        let ports: Vec<u16> = (0..8).map(|_| OsEntropy.source_port()).collect();
+        assert!(ports.iter().all(|port| *port >= MIN_SOURCE_PORT));
+        assert!(ports.iter().any(|port| *port != ports[0]));
+    }
+}
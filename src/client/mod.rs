@@ -0,0 +1,814 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+
+use self::entropy::{Entropy, OsEntropy};
+use crate::error::DnsError;
+use crate::message::{message::Message, response_code::ResponseCode};
+use crate::rr::record::Record;
+
+pub mod entropy;
+pub mod tcp;
+pub mod udp;
+
+/// The port name servers listen on.
+pub const DNS_PORT: u16 = 53;
+
+/// How long to wait for a response, as in resolv.conf(5).
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many times a query is sent before giving up, as in resolv.conf(5).
+pub const DEFAULT_ATTEMPTS: usize = 2;
+
+/// The delay before retrying a query that timed out, multiplied by `factor`
+/// after every retry and capped to `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub factor: u32,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// Retry right away.
+    pub const NONE: Backoff = Backoff {
+        initial: Duration::ZERO,
+        factor: 1,
+        max: Duration::ZERO,
+    };
+
+    /// The delay before the `retry`th retry, counting from 1.
+    pub fn delay(&self, retry: usize) -> Duration {
+        let mut delay = self.initial;
+        for _ in 1..retry {
+            if delay >= self.max {
+                break;
+            }
+            delay = delay.saturating_mul(self.factor);
+        }
+        delay.min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    /// 100ms, then 200ms, 400ms... up to 2s.
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            factor: 2,
+            max: Duration::from_secs(2),
+        }
+    }
+}
+
+/// How messages are carried to the name server (RFC 1035 4.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// A single datagram each way, limited to 512 bytes unless the query
+    /// advertises a larger payload with EDNS(0).
+    Udp,
+    /// A connection carrying messages prefixed with their length.
+    Tcp,
+}
+
+/// A response, along with where and how it was received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub message: Message,
+    /// The name server which sent the response.
+    pub server: SocketAddr,
+    /// TCP when the query was sent over TCP, or retried over it after a truncated UDP response.
+    pub transport: Transport,
+}
+
+/// The messages of a zone transfer, along with the name server which sent them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    /// The messages in the order received, which hold the records of the zone
+    /// from its SOA record to the same again.
+    pub messages: Vec<Message>,
+    pub server: SocketAddr,
+}
+
+impl Transfer {
+    /// The records of the zone, across every message.
+    pub fn records(&self) -> impl Iterator<Item = &Record> {
+        self.messages.iter().flat_map(|message| &message.answer)
+    }
+}
+
+/// Sends queries to a single name server.
+#[derive(Debug, Clone)]
+pub struct Client {
+    server: SocketAddr,
+    transport: Transport,
+    retry_truncated: bool,
+    timeout: Duration,
+    attempts: usize,
+    backoff: Backoff,
+    entropy: Arc<dyn Entropy>,
+}
+
+impl Client {
+    /// A client querying `server` over UDP.
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server,
+            transport: Transport::Udp,
+            retry_truncated: true,
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+            backoff: Backoff::default(),
+            entropy: Arc::new(OsEntropy),
+        }
+    }
+
+    /// Query over `transport` instead.
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Whether a truncated UDP response is retried over TCP, which is the default.
+    /// When not, the truncated response is returned as is.
+    pub fn with_retry_truncated(mut self, retry_truncated: bool) -> Self {
+        self.retry_truncated = retry_truncated;
+        self
+    }
+
+    /// Give up on a response after `timeout` instead of [`DEFAULT_TIMEOUT`].
+    /// This applies to every attempt.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send a query up to `attempts` times instead of [`DEFAULT_ATTEMPTS`].
+    /// A query is always sent at least once.
+    pub fn with_attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Wait according to `backoff` between attempts instead of [`Backoff::default`].
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Draw query ids and source ports from `entropy` instead of [`OsEntropy`].
+    pub fn with_entropy(mut self, entropy: impl Entropy + 'static) -> Self {
+        self.entropy = Arc::new(entropy);
+        self
+    }
+
+    /// The name server queries are sent to.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// The transport queries are sent over.
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    /// Send `query` to the name server, and wait for its response.
+    ///
+    /// The id of `query` is replaced by a random one. Only a message from the
+    /// name server with that id and the questions of `query` is accepted as its
+    /// response, others are dropped until the timeout. The query is then sent
+    /// again, until it was sent as many times as configured.
+    pub async fn query(&self, query: &Message) -> Result<Response, DnsError> {
+        for attempt in 1..=self.attempts {
+            if attempt > 1 {
+                tokio::time::sleep(self.backoff.delay(attempt - 1)).await;
+            }
+            match tokio::time::timeout(self.timeout, self.attempt(query)).await {
+                Ok(response) => return response,
+                Err(_) => warn!(
+                    "No response from {} to attempt {} of {}",
+                    self.server, attempt, self.attempts
+                ),
+            }
+        }
+        Err(DnsError::Timeout(self.attempts))
+    }
+
+    /// Transfer the zone asked for by `query`, an AXFR query, over TCP whatever
+    /// the transport.
+    ///
+    /// The id of `query` is replaced by a random one. It is sent only once,
+    /// and the timeout applies to each message of the response.
+    pub async fn transfer(&self, query: &Message) -> Result<Transfer, DnsError> {
+        let mut query = query.clone();
+        query.header.id = self.entropy.query_id();
+        debug!("Query id : {}", query.header.id);
+        Ok(Transfer {
+            messages: tcp::transfer(self.server, &query, self.timeout).await?,
+            server: self.server,
+        })
+    }
+
+    /// Send `query` once with a fresh id, then again without its EDNS(0)
+    /// parameters if the name server answers FORMERR, as servers which do
+    /// not implement EDNS may (RFC 6891 7).
+    async fn attempt(&self, query: &Message) -> Result<Response, DnsError> {
+        let mut query = query.clone();
+        query.header.id = self.entropy.query_id();
+        let response = self.send(&query).await?;
+        if response.message.response_code() != ResponseCode::FormatError || query.edns.is_none() {
+            return Ok(response);
+        }
+        info!("{} answered FORMERR, retrying without EDNS", self.server);
+        query.set_edns(None);
+        query.header.id = self.entropy.query_id();
+        self.send(&query).await
+    }
+
+    /// Send `query` as is, over TCP when the UDP response is truncated.
+    async fn send(&self, query: &Message) -> Result<Response, DnsError> {
+        debug!("Query id : {}", query.header.id);
+
+        if self.transport == Transport::Udp {
+            let message = udp::query(self.server, query, self.entropy.as_ref()).await?;
+            if !(message.header.truncation && self.retry_truncated) {
+                return Ok(Response {
+                    message,
+                    server: self.server,
+                    transport: Transport::Udp,
+                });
+            }
+            info!("Response is truncated, retrying over TCP");
+        }
+        Ok(Response {
+            message: tcp::query(self.server, query).await?,
+            server: self.server,
+            transport: Transport::Tcp,
+        })
+    }
+}
+
+/// The local address to bind to, in the same family as `server`, with an ephemeral port.
+fn unspecified_addr(server: SocketAddr) -> SocketAddr {
+    let ip = match server {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    SocketAddr::new(ip, 0)
+}
+
+#[cfg(test)]
+mod tests_client {
+    use super::entropy::Fixed;
+    use super::*;
+    use crate::message::{edns::Edns, message::MAX_UDP_BYTES};
+    use crate::rr::{rdata::RData, record_class::Class, record_type::RecordType};
+    use tokio::net::{TcpListener, UdpSocket};
+
+    /// A response to `query` with an A record for the question.
+    fn answer(query: &[u8]) -> Message {
+        let mut response = Message::deserialize(query).unwrap();
+        response.header.is_response = true;
+        response.answer.push(Record {
+            name: response.question[0].name.clone(),
+            record_type: RecordType::A,
+            class: Class::IN,
+            ttl: 60,
+            rdata: RData::A(Ipv4Addr::new(10, 0, 0, 1)),
+        });
+        response
+    }
+
+    /// Answer the first query received on `socket`.
+    async fn answer_once(socket: UdpSocket) {
+        let mut buf = vec![0; MAX_UDP_BYTES];
+        let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+        let response = answer(&buf[..len]);
+        socket
+            .send_to(&response.as_vec().unwrap(), from)
+            .await
+            .unwrap();
+    }
+
+    /// Answer the first query received on `socket` with a truncated response:
+    /// empty, or cut in the middle of its record when `cut` is set.
+    async fn answer_once_truncated(socket: UdpSocket, cut: bool) {
+        let mut buf = vec![0; MAX_UDP_BYTES];
+        let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+        let mut response = match cut {
+            true => answer(&buf[..len]),
+            false => Message::deserialize(&buf[..len]).unwrap(),
+        };
+        response.header.is_response = true;
+        response.header.truncation = true;
+        let mut response = response.as_vec().unwrap();
+        if cut {
+            response.truncate(response.len() - 2);
+        }
+        socket.send_to(&response, from).await.unwrap();
+    }
+
+    /// Answer the first query received on the first connection to `listener`,
+    /// padding the response with TXT records so it does not fit in a datagram.
+    async fn answer_once_tcp(listener: TcpListener) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let query = tcp::read_message(&mut stream).await.unwrap();
+        let mut response = answer(&query);
+        for _ in 0..4 {
+            response.additional.push(Record {
+                name: response.question[0].name.clone(),
+                record_type: RecordType::TXT,
+                class: Class::IN,
+                ttl: 60,
+                rdata: RData::TXT(vec![vec![b'x'; 200]]),
+            });
+        }
+        tcp::write_message(&mut stream, &response.as_vec().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_query_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap());
+        tokio::spawn(answer_once(server));
+
+        let query = Message::new(7, "example.com.", RecordType::A, Class::IN).unwrap();
+        let Response {
+            message,
+            server,
+            transport,
+        } = client.query(&query).await.unwrap();
+        assert_eq!(server, client.server());
+        assert_eq!(transport, Transport::Udp);
+        assert_eq!(message.question, query.question);
+        assert_eq!(
+            message.answer[0].rdata,
+            RData::A(Ipv4Addr::new(10, 0, 0, 1))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_tcp() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap()).with_transport(Transport::Tcp);
+        tokio::spawn(answer_once_tcp(server));
+
+        let query = Message::new(8, "example.com.", RecordType::A, Class::IN).unwrap();
+        let Response {
+            message, transport, ..
+        } = client.query(&query).await.unwrap();
+        assert_eq!(transport, Transport::Tcp);
+        assert_eq!(
+            message.answer[0].rdata,
+            RData::A(Ipv4Addr::new(10, 0, 0, 1))
+        );
+        assert_eq!(message.additional.len(), 4);
+    }
+
+    /// Send stray packets to the client that sent the first query received on
+    /// `socket`: from another address, with another id, with another question
+    /// and without the QR bit. Then answer it when `then_answer` is set.
+    async fn answer_after_strays(socket: UdpSocket, then_answer: bool) {
+        let mut buf = vec![0; MAX_UDP_BYTES];
+        let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+        let query = Message::deserialize(&buf[..len]).unwrap();
+        let response = answer(&buf[..len]);
+
+        let spoofer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        spoofer
+            .send_to(&response.as_vec().unwrap(), from)
+            .await
+            .unwrap();
+
+        let mut strays = vec![response.clone(), response.clone(), query];
+        strays[0].header.id = response.header.id.wrapping_add(1);
+        strays[1].question[0].name = "example.org.".to_string();
+        for stray in strays {
+            socket
+                .send_to(&stray.as_vec().unwrap(), from)
+                .await
+                .unwrap();
+        }
+        socket.send_to(&[0xff; 3], from).await.unwrap();
+        if then_answer {
+            socket
+                .send_to(&response.as_vec().unwrap(), from)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drop_stray_packets() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap());
+        tokio::spawn(answer_after_strays(server, true));
+
+        let query = Message::new(11, "example.com.", RecordType::A, Class::IN).unwrap();
+        let Response { message, .. } = client.query(&query).await.unwrap();
+        assert_eq!(message.answer.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_on_stray_packets() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap())
+            .with_timeout(Duration::from_millis(200))
+            .with_attempts(1);
+        tokio::spawn(answer_after_strays(server, false));
+
+        let query = Message::new(12, "example.com.", RecordType::A, Class::IN).unwrap();
+        assert_eq!(
+            client.query(&query).await.unwrap_err(),
+            DnsError::Timeout(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_id_and_source_port() {
+        // a source port known to be free, which the entropy leads to
+        let port = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let value = port - 1024;
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap()).with_entropy(Fixed(value));
+        let received = tokio::spawn(async move {
+            let mut buf = vec![0; MAX_UDP_BYTES];
+            let (len, from) = server.recv_from(&mut buf).await.unwrap();
+            server
+                .send_to(&answer(&buf[..len]).as_vec().unwrap(), from)
+                .await
+                .unwrap();
+            (Message::deserialize(&buf[..len]).unwrap().header.id, from)
+        });
+
+        let query = Message::new(42, "example.com.", RecordType::A, Class::IN).unwrap();
+        let Response { message, .. } = client.query(&query).await.unwrap();
+        assert_eq!(message.header.id, value);
+        let (id, from) = received.await.unwrap();
+        assert_eq!(id, value);
+        assert_eq!(from.port(), port);
+    }
+
+    #[tokio::test]
+    async fn test_random_query_ids() {
+        let query = Message::new(42, "example.com.", RecordType::A, Class::IN).unwrap();
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let client = Client::new(server.local_addr().unwrap());
+            tokio::spawn(answer_once(server));
+            ids.push(client.query(&query).await.unwrap().message.header.id);
+        }
+        assert!(ids.iter().any(|id| *id != ids[0]));
+    }
+
+    /// Drop the first `drop` queries received on `socket`, then answer the next one.
+    /// Returns the time each query was received at.
+    async fn answer_after_drops(socket: UdpSocket, drop: usize) -> Vec<tokio::time::Instant> {
+        let mut buf = vec![0; MAX_UDP_BYTES];
+        let mut received = Vec::new();
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+            received.push(tokio::time::Instant::now());
+            if received.len() > drop {
+                let response = answer(&buf[..len]);
+                socket
+                    .send_to(&response.as_vec().unwrap(), from)
+                    .await
+                    .unwrap();
+                return received;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_dropped_queries() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backoff = Backoff {
+            initial: Duration::from_millis(50),
+            factor: 2,
+            max: Duration::from_secs(1),
+        };
+        let client = Client::new(server.local_addr().unwrap())
+            .with_timeout(Duration::from_millis(100))
+            .with_attempts(3)
+            .with_backoff(backoff);
+        let received = tokio::spawn(answer_after_drops(server, 2));
+
+        let query = Message::new(13, "example.com.", RecordType::A, Class::IN).unwrap();
+        let Response { message, .. } = client.query(&query).await.unwrap();
+        assert_eq!(message.answer.len(), 1);
+
+        let received = received.await.unwrap();
+        assert_eq!(received.len(), 3);
+        // the timeout, then the backoff delay
+        assert!(received[1] - received[0] >= Duration::from_millis(150));
+        assert!(received[2] - received[1] >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_after_attempts() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap())
+            .with_timeout(Duration::from_millis(50))
+            .with_attempts(3)
+            .with_backoff(Backoff::NONE);
+        let received = tokio::spawn(answer_after_drops(server, 3));
+
+        let query = Message::new(14, "example.com.", RecordType::A, Class::IN).unwrap();
+        assert_eq!(
+            client.query(&query).await.unwrap_err(),
+            DnsError::Timeout(3)
+        );
+        received.abort();
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let backoff = Backoff::default();
+        let delays: Vec<u128> = (1..=7)
+            .map(|retry| backoff.delay(retry).as_millis())
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1600, 2000, 2000]);
+        assert_eq!(Backoff::NONE.delay(3), Duration::ZERO);
+        let huge = Backoff {
+            factor: u32::MAX,
+            max: Duration::MAX,
+            ..backoff
+        };
+        assert_eq!(huge.delay(100), Duration::MAX);
+    }
+
+    #[test]
+    fn test_at_least_one_attempt() {
+        let client = Client::new("127.0.0.1:53".parse().unwrap()).with_attempts(0);
+        assert_eq!(client.attempts, 1);
+    }
+
+    /// A UDP and a TCP socket listening on the same local port.
+    async fn bind_udp_and_tcp() -> (UdpSocket, TcpListener) {
+        loop {
+            let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            if let Ok(udp) = UdpSocket::bind(tcp.local_addr().unwrap()).await {
+                return (udp, tcp);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_truncated_over_tcp() {
+        for cut in [false, true] {
+            let (udp, tcp) = bind_udp_and_tcp().await;
+            let client = Client::new(udp.local_addr().unwrap());
+            tokio::spawn(answer_once_truncated(udp, cut));
+            tokio::spawn(answer_once_tcp(tcp));
+
+            let query = Message::new(9, "example.com.", RecordType::A, Class::IN).unwrap();
+            let Response {
+                message, transport, ..
+            } = client.query(&query).await.unwrap();
+            assert_eq!(transport, Transport::Tcp);
+            assert!(!message.header.truncation);
+            assert_eq!(message.answer.len(), 1);
+            assert_eq!(message.additional.len(), 4);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_truncated() {
+        for cut in [false, true] {
+            let (udp, _tcp) = bind_udp_and_tcp().await;
+            let client = Client::new(udp.local_addr().unwrap()).with_retry_truncated(false);
+            tokio::spawn(answer_once_truncated(udp, cut));
+
+            let query = Message::new(10, "example.com.", RecordType::A, Class::IN).unwrap();
+            let Response {
+                message, transport, ..
+            } = client.query(&query).await.unwrap();
+            assert_eq!(transport, Transport::Udp);
+            assert!(message.header.truncation);
+            assert_eq!(message.question, query.question);
+            assert!(message.answer.is_empty());
+            assert_eq!(message.header.answer_count, 0);
+        }
+    }
+
+    /// Answer queries on `socket` like a name server not implementing EDNS(0):
+    /// FORMERR when they have an OPT record, with the question only when
+    /// `with_question` is set, and an A record otherwise.
+    async fn answer_without_edns(socket: UdpSocket, with_question: bool) {
+        let mut buf = vec![0; MAX_UDP_BYTES];
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+            let mut response = answer(&buf[..len]);
+            if response.edns.take().is_some() {
+                response.answer.clear();
+                if !with_question {
+                    response.question.clear();
+                }
+                response.header.resp_code = ResponseCode::FormatError;
+            }
+            let _ = socket.send_to(&response.as_vec().unwrap(), from).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_without_edns() {
+        for with_question in [true, false] {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let client = Client::new(server.local_addr().unwrap());
+            tokio::spawn(answer_without_edns(server, with_question));
+
+            let mut query = Message::new(11, "example.com.", RecordType::A, Class::IN).unwrap();
+            query.set_edns(Some(Edns::default()));
+            let Response { message, .. } = client.query(&query).await.unwrap();
+            assert_eq!(message.response_code(), ResponseCode::NoError);
+            assert_eq!(message.answer.len(), 1);
+            assert!(message.edns.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_edns_payload_size() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut buf = vec![0; MAX_UDP_BYTES];
+            let (len, from) = server.recv_from(&mut buf).await.unwrap();
+            let mut response = answer(&buf[..len]);
+            response.answer[0].rdata = RData::TXT(vec![vec![b'x'; 255]; 4]);
+            response.answer[0].record_type = RecordType::TXT;
+            let response = response.as_vec().unwrap();
+            assert!(response.len() > MAX_UDP_BYTES);
+            server.send_to(&response, from).await.unwrap();
+        });
+
+        let mut query = Message::new(12, "example.com.", RecordType::TXT, Class::IN).unwrap();
+        query.set_edns(Some(Edns::new(4096)));
+        let Response {
+            message, transport, ..
+        } = client.query(&query).await.unwrap();
+        assert_eq!(transport, Transport::Udp);
+        assert_eq!(message.edns, Some(Edns::new(4096)));
+        assert_eq!(
+            message.answer[0].rdata,
+            RData::TXT(vec![vec![b'x'; 255]; 4])
+        );
+    }
+
+    /// A record of the zone example.com.
+    fn zone_record(name: &str, rdata: RData) -> Record {
+        Record {
+            name: name.to_string(),
+            record_type: rdata.record_type(),
+            class: Class::IN,
+            ttl: 3600,
+            rdata,
+        }
+    }
+
+    /// Answer the zone transfer query received on the first connection to
+    /// `listener` with the records of example.com. over three messages, the
+    /// later ones without the question, and a stray message in between. Then
+    /// keep the connection open until the client closes it.
+    async fn transfer_once(listener: TcpListener) {
+        let soa = zone_record(
+            "example.com.",
+            RData::SOA {
+                mname: "ns.example.com.".to_string(),
+                rname: "hostmaster.example.com.".to_string(),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 60,
+            },
+        );
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let query = tcp::read_message(&mut stream).await.unwrap();
+        let mut first = Message::deserialize(&query).unwrap();
+        first.header.is_response = true;
+        first.header.authoritative_answer = true;
+        let mut stray = first.clone();
+        stray.header.id = first.header.id.wrapping_add(1);
+        stray.set_answer(vec![soa.clone()]);
+        let mut second = first.clone();
+        second.question.clear();
+        let mut third = second.clone();
+        first.set_answer(vec![
+            soa.clone(),
+            zone_record("example.com.", RData::NS("ns.example.com.".to_string())),
+        ]);
+        second.set_answer(vec![
+            zone_record("ns.example.com.", RData::A(Ipv4Addr::new(192, 0, 2, 1))),
+            zone_record("www.example.com.", RData::A(Ipv4Addr::new(192, 0, 2, 2))),
+        ]);
+        third.set_answer(vec![soa]);
+        for message in [first, stray, second, third] {
+            tcp::write_message(&mut stream, &message.as_vec().unwrap())
+                .await
+                .unwrap();
+        }
+        let _ = tcp::read_message(&mut stream).await;
+    }
+
+    #[tokio::test]
+    async fn test_transfer() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap()).with_timeout(Duration::from_secs(1));
+        tokio::spawn(transfer_once(server));
+
+        let query = Message::new(15, "example.com.", RecordType::AXFR, Class::IN).unwrap();
+        let transfer = client.transfer(&query).await.unwrap();
+        assert_eq!(transfer.server, client.server());
+        assert_eq!(transfer.messages.len(), 3);
+        let types: Vec<RecordType> = transfer
+            .records()
+            .map(|record| record.record_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                RecordType::SOA,
+                RecordType::NS,
+                RecordType::A,
+                RecordType::A,
+                RecordType::SOA
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfer_without_soa() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap());
+        tokio::spawn(answer_once_tcp(server));
+
+        let query = Message::new(16, "example.com.", RecordType::AXFR, Class::IN).unwrap();
+        assert!(matches!(
+            client.transfer(&query).await.unwrap_err(),
+            DnsError::BadTransfer(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_empty() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap()).with_timeout(Duration::from_secs(1));
+        tokio::spawn(async move {
+            let (mut stream, _) = server.accept().await.unwrap();
+            let query = tcp::read_message(&mut stream).await.unwrap();
+            let mut response = Message::deserialize(&query).unwrap();
+            response.header.is_response = true;
+            tcp::write_message(&mut stream, &response.as_vec().unwrap())
+                .await
+                .unwrap();
+            // keep the connection open until the client closes it
+            let _ = tcp::read_message(&mut stream).await;
+        });
+
+        let query = Message::new(18, "example.com.", RecordType::AXFR, Class::IN).unwrap();
+        assert_eq!(
+            client.transfer(&query).await.unwrap_err(),
+            DnsError::BadTransfer("does not start with an SOA record")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfer_refused() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(server.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = server.accept().await.unwrap();
+            let query = tcp::read_message(&mut stream).await.unwrap();
+            let mut response = Message::deserialize(&query).unwrap();
+            response.header.is_response = true;
+            response.header.resp_code = ResponseCode::Refused;
+            tcp::write_message(&mut stream, &response.as_vec().unwrap())
+                .await
+                .unwrap();
+        });
+
+        let query = Message::new(17, "example.com.", RecordType::AXFR, Class::IN).unwrap();
+        let transfer = client.transfer(&query).await.unwrap();
+        assert_eq!(transfer.messages.len(), 1);
+        assert_eq!(transfer.messages[0].response_code(), ResponseCode::Refused);
+    }
+
+    #[test]
+    fn test_unspecified_addr() {
+        let v6: SocketAddr = "[::1]:53".parse().unwrap();
+        assert_eq!(unspecified_addr(v6), "[::]:0".parse().unwrap());
+        let v4: SocketAddr = "8.8.8.8:53".parse().unwrap();
+        assert_eq!(unspecified_addr(v4), "0.0.0.0:0".parse().unwrap());
+    }
+}
@@ -0,0 +1,171 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, trace, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::error::DnsError;
+use crate::message::{message::Message, response_code::ResponseCode};
+use crate::rr::record_type::RecordType;
+
+/// Send `query` to `server` over a new TCP connection, and wait for the response.
+/// Messages which are not a response to `query` are dropped.
+pub async fn query(server: SocketAddr, query: &Message) -> Result<Message, DnsError> {
+    let mut stream = TcpStream::connect(server).await?;
+
+    let body: Vec<u8> = query.as_vec()?;
+    trace!("bytes to send : {}", hex::encode(&body));
+    write_message(&mut stream, &body).await?;
+
+    // The connection is to `server` itself, but it may still send other messages first.
+    loop {
+        let response = read_message(&mut stream).await?;
+        debug!("received {} bytes", response.len());
+        let response = Message::deserialize(&response)?;
+        if response.is_response_to(query) {
+            return Ok(response);
+        }
+        warn!("Dropping a message which is not a response to the query");
+    }
+}
+
+/// Send the zone transfer `query` to `server` over a new TCP connection, and
+/// read the messages of the response until the SOA record which closes the
+/// zone, as it may span several of them (RFC 5936 2.2). Each step waits up to
+/// `timeout`.
+///
+/// Messages after the first may leave out the question (RFC 5936 2.2.1). A
+/// response other than NOERROR ends the transfer, as the last message.
+pub async fn transfer(
+    server: SocketAddr,
+    query: &Message,
+    timeout: Duration,
+) -> Result<Vec<Message>, DnsError> {
+    let timed_out = |_| DnsError::Timeout(1);
+    let mut stream = tokio::time::timeout(timeout, TcpStream::connect(server))
+        .await
+        .map_err(timed_out)??;
+
+    let body: Vec<u8> = query.as_vec()?;
+    trace!("bytes to send : {}", hex::encode(&body));
+    write_message(&mut stream, &body).await?;
+
+    let mut messages = Vec::new();
+    let mut records = 0;
+    loop {
+        let response = tokio::time::timeout(timeout, read_message(&mut stream))
+            .await
+            .map_err(timed_out)??;
+        debug!("received {} bytes", response.len());
+        let response = Message::deserialize(&response)?;
+        let continued = !messages.is_empty()
+            && response.question.is_empty()
+            && response.header.id == query.header.id
+            && response.header.is_response;
+        if !(continued || response.is_response_to(query)) {
+            warn!("Dropping a message which is not a response to the query");
+            continue;
+        }
+        if response.response_code() != ResponseCode::NoError {
+            messages.push(response);
+            return Ok(messages);
+        }
+
+        let starts_with_soa = response
+            .answer
+            .first()
+            .is_some_and(|record| record.record_type == RecordType::SOA);
+        if records == 0 && !starts_with_soa {
+            return Err(DnsError::BadTransfer("does not start with an SOA record"));
+        }
+        let mut closed = false;
+        for record in &response.answer {
+            closed |= records > 0 && record.record_type == RecordType::SOA;
+            records += 1;
+        }
+        messages.push(response);
+        if closed {
+            debug!(
+                "Transferred {records} records in {} messages",
+                messages.len()
+            );
+            return Ok(messages);
+        }
+    }
+}
+
+/// Write `body` prefixed with its length as a two byte field (RFC 1035 4.2.2).
+pub async fn write_message<W>(stream: &mut W, body: &[u8]) -> Result<(), DnsError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let len = u16::try_from(body.len()).map_err(|_| {
+        DnsError::Io(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Message is {} bytes, which is over the max of 65535",
+                body.len()
+            ),
+        )
+    })?;
+    let mut framed = Vec::with_capacity(body.len() + 2);
+    framed.extend_from_slice(&len.to_be_bytes());
+    framed.extend_from_slice(body);
+    stream.write_all(&framed).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Read a message prefixed with its length as a two byte field (RFC 1035 4.2.2).
+pub async fn read_message<R>(stream: &mut R) -> Result<Vec<u8>, DnsError>
+where
+    R: AsyncRead + Unpin,
+{
+    let len = stream.read_u16().await?;
+    let mut body = vec![0; usize::from(len)];
+    stream.read_exact(&mut body).await?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests_tcp {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_framing_round_trip() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        write_message(&mut client, &[1, 2, 3]).await.unwrap();
+        write_message(&mut client, &[]).await.unwrap();
+        assert_eq!(read_message(&mut server).await.unwrap(), vec![1, 2, 3]);
+        assert_eq!(read_message(&mut server).await.unwrap(), Vec::<u8>::new());
+    }
+
+    #[tokio::test]
+    async fn test_length_prefix() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        write_message(&mut client, &[0xaa; 300]).await.unwrap();
+        let mut prefix = [0; 2];
+        server.read_exact(&mut prefix).await.unwrap();
+        assert_eq!(prefix, [1, 44]);
+    }
+
+    #[tokio::test]
+    async fn test_read_truncated() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client.write_all(&[0, 10, 1, 2]).await.unwrap();
+        drop(client);
+        let err = read_message(&mut server).await.unwrap_err();
+        assert!(matches!(
+            err,
+            DnsError::Io(std::io::ErrorKind::UnexpectedEof, _)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_write_too_long() {
+        let (mut client, _server) = tokio::io::duplex(1024);
+        let body = vec![0; 65536];
+        assert!(write_message(&mut client, &body).await.is_err());
+    }
+}
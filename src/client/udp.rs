@@ -0,0 +1,104 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+
+use log::{debug, trace, warn};
+use tokio::net::UdpSocket;
+
+use super::{entropy::Entropy, unspecified_addr};
+use crate::error::DnsError;
+use crate::message::{
+    edns::Edns,
+    header::MessageHeader,
+    message::{Message, MAX_UDP_BYTES},
+};
+
+/// How many random source ports are tried before letting the OS pick one.
+const BIND_ATTEMPTS: usize = 8;
+
+/// Send `query` to `server` in a single datagram from a random source port,
+/// and wait for the response.
+///
+/// Packets that do not come from `server` or are not a response to `query`
+/// are dropped, so this only returns once the actual response arrives. A
+/// truncated response whose records cannot be parsed, as it may be cut
+/// anywhere, is returned with its header and the questions of `query` alone.
+/// Responses up to the payload size advertised by the EDNS(0) parameters of
+/// `query` are received, or else up to 512 bytes.
+pub async fn query(
+    server: SocketAddr,
+    query: &Message,
+    entropy: &dyn Entropy,
+) -> Result<Message, DnsError> {
+    let socket = bind(server, entropy).await?;
+
+    let body: Vec<u8> = query.as_vec()?;
+    trace!("bytes to send : {}", hex::encode(&body));
+    let bytes_sent = socket.send_to(&body, server).await?;
+    if bytes_sent != body.len() {
+        return Err(DnsError::Io(
+            std::io::ErrorKind::WriteZero,
+            format!("Only {bytes_sent} bytes sent, message was probably truncated"),
+        ));
+    }
+
+    let max_payload = query.edns.as_ref().map_or(MAX_UDP_BYTES, Edns::max_payload);
+    let mut response_buf = vec![0; max_payload];
+    loop {
+        let (received, from) = socket.recv_from(&mut response_buf).await?;
+        debug!("received {} bytes from {}", received, from);
+        if from != server {
+            warn!("Dropping a packet from {}, which was not queried", from);
+            continue;
+        }
+        let packet = &response_buf[..received];
+        let header = match MessageHeader::try_from(packet.to_vec()) {
+            Ok(header) => header,
+            Err(e) => {
+                warn!("Dropping a malformed packet: {e}");
+                continue;
+            }
+        };
+        if header.id != query.header.id || !header.is_response {
+            warn!("Dropping a packet which is not a response to the query");
+            continue;
+        }
+        match Message::deserialize(packet) {
+            Ok(response) if response.is_response_to(query) => return Ok(response),
+            Ok(_) => warn!("Dropping a packet which is not a response to the query"),
+            Err(e) if header.truncation => {
+                debug!("Ignoring the records of a truncated response: {e}");
+                return Ok(truncated(query, header));
+            }
+            Err(e) => warn!("Dropping a malformed packet: {e}"),
+        }
+    }
+}
+
+/// The response to `query` made of `header` alone, with the counts of its sections.
+fn truncated(query: &Message, header: MessageHeader) -> Message {
+    let mut response = Message {
+        header,
+        question: query.question.clone(),
+        answer: Vec::new(),
+        authority: Vec::new(),
+        additional: Vec::new(),
+        edns: None,
+    };
+    response.update_counts();
+    response
+}
+
+/// Bind a socket to a source port from `entropy`, in the family of `server`.
+async fn bind(server: SocketAddr, entropy: &dyn Entropy) -> Result<UdpSocket, DnsError> {
+    let mut local = unspecified_addr(server);
+    for _ in 0..BIND_ATTEMPTS {
+        local.set_port(entropy.source_port());
+        match UdpSocket::bind(local).await {
+            Ok(socket) => return Ok(socket),
+            Err(e) if e.kind() == ErrorKind::AddrInUse => debug!("Port {} is in use", local.port()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    local.set_port(0);
+    Ok(UdpSocket::bind(local).await?)
+}
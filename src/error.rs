@@ -0,0 +1,218 @@
+use std::fmt;
+
+use nom::error::{ErrorKind, FromExternalError, ParseError};
+
+use crate::rr::record_type::RecordType;
+
+/// Everything that can go wrong in the library: decoding or encoding a DNS
+/// message, sending it to a name server and waiting for the response,
+/// configuring the resolver, and resolving a name through aliases, referrals
+/// and zone transfers.
+///
+/// It is also the error type of the `nom` parsers, so that a malformed or
+/// hostile packet surfaces as an error instead of a panic.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DnsError {
+    /// The input ended before the message was complete.
+    Truncated,
+    /// A label is over 63 bytes, or its length octet uses the reserved 01 or 10 prefixes.
+    BadLabelLength(usize),
+    /// A label is not valid UTF-8.
+    NonUtf8Label,
+    /// A name is over the max of 255 bytes.
+    NameTooLong(usize),
+    /// A compression pointer leads back to an offset it already went through.
+    PointerLoop(usize),
+    /// A name follows more compression pointers than any legitimate name needs.
+    TooManyPointers,
+    /// A compression pointer points past the end of the message.
+    BadPointer(usize),
+    /// Bytes are left over after the end of the message or of an RDATA.
+    TrailingGarbage(usize),
+    /// A character-string is over the max of 255 bytes.
+    CharacterStringTooLong(usize),
+    /// An RDATA is over the max of 65535 bytes.
+    RDataTooLong(usize),
+    /// A section has more entries than its 16 bit count can hold.
+    TooManyRecords(usize),
+    /// A record has a type only valid in questions, such as AXFR.
+    MetaType(RecordType),
+    /// An opcode does not fit in 4 bits, or is an unassigned opcode standing
+    /// for an assigned one.
+    BadOpcode(u8),
+    /// A response code does not fit in its field, or is an unassigned code
+    /// standing for an assigned one.
+    BadResponseCode(u16),
+    /// A record type is an unknown type standing for a known one, such as
+    /// `Unknown(1)` for A.
+    BadRecordType(u16),
+    /// A class is an unknown class standing for a known one, such as
+    /// `Unknown(1)` for IN.
+    BadClass(u16),
+    /// A lookup was asked for a zone transfer type, such as AXFR.
+    ZoneTransfer(RecordType),
+    /// A zone transfer does not start with the SOA record of the zone.
+    BadTransfer(&'static str),
+    /// The OPT pseudo-record is malformed, or there is more than one (RFC 6891 6.1.1).
+    BadOpt(&'static str),
+    /// Any other parser failure.
+    Parse(ErrorKind),
+    /// Sending or receiving a message failed.
+    Io(std::io::ErrorKind, String),
+    /// No matching response was received from the name server in time,
+    /// after the given number of attempts.
+    Timeout(usize),
+    /// The resolver configuration is not usable.
+    InvalidConfig(String),
+    /// A chain of CNAME or DNAME records leads back to a name it already went through.
+    AliasLoop(String),
+    /// A chain of CNAME or DNAME records is longer than any sensible zone needs.
+    TooManyAliases { len: usize, max: usize },
+    /// A name server of the given zone neither answered authoritatively nor
+    /// referred to a zone closer to the name.
+    NoReferral(String),
+    /// No address could be found for any name server of the given zone.
+    UnreachableZone(String),
+    /// Resolving a name from the root went through more referrals than any
+    /// sensible delegation needs.
+    TooManyReferrals(usize),
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsError::Truncated => write!(f, "Message is truncated"),
+            DnsError::BadLabelLength(len) => {
+                write!(
+                    f,
+                    "DNS name labels must be <=63 bytes but this one is {len}"
+                )
+            }
+            DnsError::NonUtf8Label => write!(f, "Label is not valid UTF-8"),
+            DnsError::NameTooLong(len) => {
+                write!(f, "Name is {len} bytes, which is over the max of 255")
+            }
+            DnsError::PointerLoop(offset) => {
+                write!(f, "Compression pointer to {offset} loops")
+            }
+            DnsError::TooManyPointers => write!(f, "Name has too many compression pointers"),
+            DnsError::BadPointer(offset) => {
+                write!(f, "Compression pointer to {offset} is out of the message")
+            }
+            DnsError::TrailingGarbage(len) => write!(f, "{len} unexpected trailing bytes"),
+            DnsError::CharacterStringTooLong(len) => {
+                write!(
+                    f,
+                    "Character string is {len} bytes, which is over the max of 255"
+                )
+            }
+            DnsError::RDataTooLong(len) => {
+                write!(f, "RDATA is {len} bytes, which does not fit RDLENGTH")
+            }
+            DnsError::TooManyRecords(len) => {
+                write!(
+                    f,
+                    "Section has {len} entries, which is over the max of 65535"
+                )
+            }
+            DnsError::MetaType(record_type) => {
+                write!(f, "{record_type} is only valid as the type of a question")
+            }
+            DnsError::BadOpcode(value) => write!(f, "Invalid opcode {value}"),
+            DnsError::BadResponseCode(value) => write!(f, "Invalid response code {value}"),
+            DnsError::BadRecordType(value) => write!(f, "Invalid record type {value}"),
+            DnsError::BadClass(value) => write!(f, "Invalid class {value}"),
+            DnsError::ZoneTransfer(record_type) => {
+                write!(f, "{record_type} is a zone transfer, not a lookup")
+            }
+            DnsError::BadTransfer(e) => write!(f, "Zone transfer {e}"),
+            DnsError::BadOpt(e) => write!(f, "OPT record {e}"),
+            DnsError::Parse(kind) => write!(f, "Parsing failed: {}", kind.description()),
+            DnsError::Io(_, e) => write!(f, "I/O error: {e}"),
+            DnsError::Timeout(attempts) => {
+                write!(
+                    f,
+                    "Timed out waiting for a response after {attempts} attempts"
+                )
+            }
+            DnsError::InvalidConfig(e) => write!(f, "Invalid configuration: {e}"),
+            DnsError::AliasLoop(name) => write!(f, "Aliases loop back to {name}"),
+            DnsError::TooManyAliases { len, max } => {
+                write!(f, "Chain of {len} aliases is over the max of {max}")
+            }
+            DnsError::NoReferral(zone) => {
+                write!(
+                    f,
+                    "Name server of {zone} did not answer nor refer to a closer zone"
+                )
+            }
+            DnsError::UnreachableZone(zone) => {
+                write!(f, "No address for any name server of {zone}")
+            }
+            DnsError::TooManyReferrals(len) => write!(f, "Followed over {len} referrals"),
+        }
+    }
+}
+
+impl std::error::Error for DnsError {}
+
+impl<I> ParseError<I> for DnsError {
+    fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::Eof | ErrorKind::Complete => DnsError::Truncated,
+            kind => DnsError::Parse(kind),
+        }
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I> FromExternalError<I, DnsError> for DnsError {
+    fn from_external_error(_input: I, _kind: ErrorKind, e: DnsError) -> Self {
+        e
+    }
+}
+
+/// Lets bit level parsers be embedded in byte level ones with `nom::bits::bits`.
+impl nom::ErrorConvert<DnsError> for DnsError {
+    fn convert(self) -> DnsError {
+        self
+    }
+}
+
+impl From<std::io::Error> for DnsError {
+    fn from(e: std::io::Error) -> Self {
+        DnsError::Io(e.kind(), e.to_string())
+    }
+}
+
+impl From<nom::Err<DnsError>> for DnsError {
+    fn from(e: nom::Err<DnsError>) -> Self {
+        match e {
+            nom::Err::Incomplete(_) => DnsError::Truncated,
+            nom::Err::Error(e) | nom::Err::Failure(e) => e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_error {
+    use super::*;
+
+    #[test]
+    fn test_from_nom() {
+        let e: DnsError = nom::Err::Incomplete(nom::Needed::Unknown).into();
+        assert_eq!(e, DnsError::Truncated);
+
+        let e: DnsError = nom::Err::Failure(DnsError::PointerLoop(12)).into();
+        assert_eq!(e, DnsError::PointerLoop(12));
+    }
+
+    #[test]
+    fn test_eof_is_truncated() {
+        let res: nom::IResult<&[u8], u16, DnsError> = nom::number::complete::be_u16(&[1][..]);
+        assert_eq!(DnsError::from(res.unwrap_err()), DnsError::Truncated);
+    }
+}
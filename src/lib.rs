@@ -0,0 +1,29 @@
+//! A DNS client: message encoding and decoding (RFC 1035), a client sending
+//! queries to a name server, and a resolver failing over between several.
+//!
+//! The most used types are re-exported at the crate root.
+
+pub mod client;
+pub mod error;
+pub mod message;
+pub mod resolver;
+pub mod rr;
+
+pub use client::{Backoff, Client, Response, Transfer, Transport};
+pub use error::DnsError;
+pub use message::{
+    edns::{Edns, EdnsOption},
+    header::MessageHeader,
+    message::Message,
+    opcode::Opcode,
+    question::Question,
+    response_code::ResponseCode,
+};
+pub use resolver::{
+    cache::{Cache, CacheConfig, Cached},
+    config::{ResolverConfig, Strategy},
+    hosts::Hosts,
+    iterative::Iterative,
+    Lookup, Resolver, Source,
+};
+pub use rr::{rdata::RData, record::Record, record_class::Class, record_type::RecordType};
@@ -1,57 +1,444 @@
-use message::header::MessageHeader;
-use message::message::Message;
-use nom::{AsBytes, HexDisplay};
-use rr::{record_class::Class, record_type::RecordType};
-use tokio::net::UdpSocket;
-
-mod message;
-mod rr;
-use bitvec::prelude::*;
-use log::{error, info};
-use rand::Rng;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use dns_client::client::{Transport, DNS_PORT};
+use dns_client::resolver::{
+    config::parse_nameserver,
+    hosts::{Hosts, HOSTS_PATH},
+    iterative::Iterative,
+    resolv_conf::{self, RESOLV_CONF_PATH},
+    Lookup, Source,
+};
+use dns_client::{
+    Class, Message, Record, RecordType, Resolver, ResolverConfig, ResponseCode, Strategy, Transfer,
+};
+use log::{error, warn};
+
+const USAGE: &str = "Usage: dns_client [@server]... [-p port] [--resolv-conf file] [--hosts file]
+                  name [type] [class] [+[no]recurse] [+[no]search] [+[no]tcp] [+[no]ignore]
+                  [+timeout=seconds] [+tries=count] [+rotate|+fastest] [+[no]trace] [+[no]edns]";
+
+/// Command line arguments, in the spirit of dig.
+///
+/// Settings left unset come from the resolv.conf file.
+#[derive(Debug, PartialEq)]
+struct Args {
+    servers: Vec<SocketAddr>,
+    resolv_conf: PathBuf,
+    hosts: PathBuf,
+    name: String,
+    record_type: RecordType,
+    class: Class,
+    recursion_desired: bool,
+    search: bool,
+    transport: Option<Transport>,
+    retry_truncated: bool,
+    timeout: Option<Duration>,
+    attempts: Option<usize>,
+    strategy: Option<Strategy>,
+    /// Whether names are resolved from the root name servers down.
+    iterative: bool,
+    edns: Option<bool>,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut servers = Vec::new();
+    let mut port = DNS_PORT;
+    let mut resolv_conf = PathBuf::from(RESOLV_CONF_PATH);
+    let mut hosts = PathBuf::from(HOSTS_PATH);
+    let mut name = None;
+    let mut record_type = None;
+    let mut class = None;
+    let mut recursion_desired = true;
+    let mut search = true;
+    let mut transport = None;
+    let mut retry_truncated = true;
+    let mut timeout = None;
+    let mut attempts = None;
+    let mut strategy = None;
+    let mut iterative = false;
+    let mut edns = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if let Some(addr) = arg.strip_prefix('@') {
+            servers.push(addr.to_string());
+        } else if arg == "-p" {
+            let value = args.next().ok_or("-p needs a port")?;
+            port = value
+                .parse()
+                .map_err(|_| format!("{value} is not a valid port"))?;
+        } else if arg == "--resolv-conf" {
+            resolv_conf = args.next().ok_or("--resolv-conf needs a file")?.into();
+        } else if arg == "--hosts" {
+            hosts = args.next().ok_or("--hosts needs a file")?.into();
+        } else if let Some(flag) = arg.strip_prefix('+') {
+            if let Some(value) = flag.strip_prefix("timeout=") {
+                let seconds = value
+                    .parse()
+                    .map_err(|_| format!("{value} is not a valid timeout"))?;
+                timeout = Some(Duration::from_secs(seconds));
+                continue;
+            }
+            if let Some(value) = flag.strip_prefix("tries=") {
+                let value = value
+                    .parse()
+                    .map_err(|_| format!("{value} is not a valid number of tries"))?;
+                attempts = Some(value);
+                continue;
+            }
+            match flag {
+                "recurse" => recursion_desired = true,
+                "norecurse" => recursion_desired = false,
+                "search" => search = true,
+                "nosearch" => search = false,
+                "tcp" | "vc" => transport = Some(Transport::Tcp),
+                "notcp" | "novc" => transport = Some(Transport::Udp),
+                "ignore" => retry_truncated = false,
+                "noignore" => retry_truncated = true,
+                "rotate" => strategy = Some(Strategy::RoundRobin),
+                "fastest" => strategy = Some(Strategy::Fastest),
+                "trace" => iterative = true,
+                "notrace" => iterative = false,
+                "edns" => edns = Some(true),
+                "noedns" => edns = Some(false),
+                other => return Err(format!("Unknown flag +{other}")),
+            }
+        } else if name.is_none() {
+            name = Some(arg);
+        } else if let (None, Ok(rt)) = (record_type, arg.parse::<RecordType>()) {
+            record_type = Some(rt);
+        } else if let (None, Ok(c)) = (class, arg.parse::<Class>()) {
+            class = Some(c);
+        } else {
+            return Err(format!("Unexpected argument {arg}"));
+        }
+    }
+
+    // An IXFR query carries the SOA record of the zone version already held,
+    // which there is no argument for (RFC 1995 3).
+    if record_type == Some(RecordType::IXFR) {
+        return Err("IXFR is not supported, transfer the whole zone with AXFR".to_string());
+    }
+
+    // The port applies to the servers given without one.
+    let servers = servers
+        .iter()
+        .map(|s| parse_nameserver(s, port).map_err(|e| e.to_string()))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Args {
+        servers,
+        resolv_conf,
+        hosts,
+        name: name.ok_or("Missing name to look up")?,
+        record_type: record_type.unwrap_or(RecordType::A),
+        class: class.unwrap_or(Class::IN),
+        recursion_desired,
+        search,
+        transport,
+        retry_truncated,
+        timeout,
+        attempts,
+        strategy,
+        iterative,
+        edns,
+    })
+}
+
+/// Apply the command line arguments over the resolver configuration `config`.
+fn override_config(mut config: ResolverConfig, args: &Args) -> ResolverConfig {
+    if !args.servers.is_empty() {
+        config.nameservers = args.servers.clone();
+    }
+    if !args.search {
+        config.search.clear();
+    }
+    config.recursion_desired = args.recursion_desired;
+    config.transport = args.transport.unwrap_or(config.transport);
+    config.retry_truncated = args.retry_truncated;
+    config.timeout = args.timeout.unwrap_or(config.timeout);
+    config.attempts = args.attempts.unwrap_or(config.attempts);
+    config.strategy = args.strategy.unwrap_or(config.strategy);
+    config.edns0 = args.edns.unwrap_or(config.edns0);
+    config
+}
+
+fn print_section(title: &str, records: &[Record]) {
+    if records.is_empty() {
+        return;
+    }
+    println!("\n;; {title} SECTION:");
+    for record in records {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            record.name, record.ttl, record.class, record.record_type, record.rdata
+        );
+    }
+}
+
+/// Print the records of `transfer` as a single section, like dig does.
+fn print_transfer(transfer: &Transfer) {
+    let records: Vec<Record> = transfer.records().cloned().collect();
+    match transfer.messages.last() {
+        Some(last) if last.response_code() != ResponseCode::NoError => print_response(last),
+        _ => print_section("ANSWER", &records),
+    }
+    println!(
+        "\n;; XFR size: {} records (messages {})",
+        records.len(),
+        transfer.messages.len()
+    );
+    println!(";; SERVER: {} (Tcp)", transfer.server);
+}
+
+fn print_response(response: &Message) {
+    let header = &response.header;
+    println!(
+        ";; ->>HEADER<<- opcode: {:?}, status: {:?}, id: {}",
+        header.opcode,
+        response.response_code(),
+        header.id
+    );
+    let flags: Vec<&str> = [
+        (header.is_response, "qr"),
+        (header.authoritative_answer, "aa"),
+        (header.truncation, "tc"),
+        (header.recursion_desired, "rd"),
+        (header.recursion_available, "ra"),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, flag)| *flag)
+    .collect();
+    println!(
+        ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+        flags.join(" "),
+        header.question_count,
+        header.answer_count,
+        header.name_server_count,
+        header.additional_records_count
+    );
+
+    if let Some(edns) = &response.edns {
+        println!("\n;; OPT PSEUDOSECTION:");
+        println!(
+            "; EDNS: version: {}, flags:{}; udp: {}",
+            edns.version,
+            if edns.dnssec_ok { " do" } else { "" },
+            edns.payload_size
+        );
+        for option in &edns.options {
+            println!("; OPTION {}: {}", option.code, hex::encode(&option.data));
+        }
+    }
+
+    println!("\n;; QUESTION SECTION:");
+    for question in &response.question {
+        println!(
+            ";{}\t\t{}\t{}",
+            question.name, question.record_qclass, question.record_type
+        );
+    }
+    print_section("ANSWER", &response.answer);
+    print_section("AUTHORITY", &response.authority);
+    print_section("ADDITIONAL", &response.additional);
+}
 
 #[tokio::main]
-async fn main() -> Result<(), std::io::Error> {
-    println!("Hello, world!");
+async fn main() -> ExitCode {
     colog::init();
 
-    let resolver = "8.8.8.8:53";
-    // let resolver = "127.0.0.1:1053";
-
-    // let query_id = rand::thread_rng().gen::<u16>();
-    let query_id = 42;
-    info!("Query id : {:?}", query_id);
-    let message: Message = Message::new(query_id, "google.com.", RecordType::A, Class::IN)
-        .expect("Could not build message");
-    let local_addr = "0.0.0.0:0";
-    let socket = UdpSocket::bind(local_addr)
-        .await
-        .expect("couldn't bind to a local address");
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
 
-    socket
-        .connect(resolver)
+    let config = resolv_conf::read(&args.resolv_conf).unwrap_or_else(|e| {
+        warn!("Could not read {}: {e}", args.resolv_conf.display());
+        resolv_conf::parse("")
+    });
+    let config = override_config(config, &args);
+    let iterative = Iterative::new()
+        .with_transport(config.transport)
+        .with_timeout(config.timeout)
+        .with_attempts(config.attempts);
+    let hosts = Hosts::read(&args.hosts).unwrap_or_else(|e| {
+        warn!("Could not read {}: {e}", args.hosts.display());
+        Hosts::default()
+    });
+    let resolver = match Resolver::new(config) {
+        Ok(resolver) if args.iterative => resolver.with_hosts(hosts).with_iterative(iterative),
+        Ok(resolver) => resolver.with_hosts(hosts),
+        Err(e) => {
+            error!("Could not configure the resolver: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // Zones are transferred over TCP on their own path, without the search
+    // list nor the cache (RFC 5936 4.2).
+    if args.record_type == RecordType::AXFR {
+        return match resolver.transfer(&args.name, args.class).await {
+            Ok(transfer) => {
+                print_transfer(&transfer);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                error!("Transfer failed: {e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    match resolver
+        .lookup(&args.name, args.record_type, args.class)
         .await
-        .expect("couldn't connect to the DNS resolver");
+    {
+        Ok(Lookup {
+            message,
+            source,
+            candidates,
+            aliases,
+            ..
+        }) => {
+            if candidates.len() > 1 {
+                println!(";; Tried {}", candidates.join(", "));
+            }
+            for alias in &aliases {
+                println!(";; Followed {} {}", alias.name, alias.rdata);
+            }
+            print_response(&message);
+            match source {
+                Source::Hosts => println!("\n;; SERVER: {}", args.hosts.display()),
+                Source::Cache => println!("\n;; SERVER: cache"),
+                Source::Server(server, transport) => {
+                    println!("\n;; SERVER: {server} ({transport:?})")
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("Lookup failed: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    // Send the DNS resolver the message
-    let body: Vec<u8> = message.as_vec();
+#[cfg(test)]
+mod tests_main {
+    use super::*;
 
-    info!("bytes to send : {}", hex::encode(body.as_bytes()));
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
 
-    let bytes_sent = socket.send(&body).await.expect("couldn't send data");
-    if bytes_sent != body.len() {
-        panic!("Only {bytes_sent} bytes, message was probably truncated");
+    #[test]
+    fn test_defaults() {
+        let args = parse(&["google.com"]).unwrap();
+        assert_eq!(
+            args,
+            Args {
+                servers: Vec::new(),
+                resolv_conf: PathBuf::from("/etc/resolv.conf"),
+                hosts: PathBuf::from("/etc/hosts"),
+                name: "google.com".to_string(),
+                record_type: RecordType::A,
+                class: Class::IN,
+                recursion_desired: true,
+                search: true,
+                transport: None,
+                retry_truncated: true,
+                timeout: None,
+                attempts: None,
+                strategy: None,
+                iterative: false,
+                edns: None,
+            }
+        );
+        let config = resolv_conf::parse(
+            "nameserver 192.0.2.1\nsearch example.com\noptions rotate timeout:1",
+        );
+        assert_eq!(override_config(config.clone(), &args), config);
     }
 
-    let mut response_buf = vec![0; message::message::MAX_UDP_BYTES];
-    match socket.recv(&mut response_buf).await {
-        Ok(received) => {
-            let i = Message::deserialize((&response_buf, received)).unwrap();
+    #[test]
+    fn test_all_arguments() {
+        let args = parse(&[
+            "@[::1]",
+            "@192.0.2.1:5353",
+            "-p",
+            "1053",
+            "--resolv-conf",
+            "/tmp/resolv.conf",
+            "--hosts",
+            "/tmp/hosts",
+            "example.org.",
+            "mx",
+            "ch",
+            "+norecurse",
+            "+nosearch",
+            "+tcp",
+            "+ignore",
+            "+timeout=1",
+            "+tries=5",
+            "+rotate",
+            "+trace",
+            "+edns",
+        ])
+        .unwrap();
+        assert_eq!(args.resolv_conf, PathBuf::from("/tmp/resolv.conf"));
+        assert_eq!(args.hosts, PathBuf::from("/tmp/hosts"));
+        assert_eq!(args.name, "example.org.");
+        assert_eq!(args.record_type, RecordType::MX);
+        assert_eq!(args.class, Class::CH);
+        assert!(!args.recursion_desired);
+        assert!(args.iterative);
 
-            info!("received {:?}", i.1);
-        }
-        Err(e) => return Err(e),
+        let config = override_config(
+            resolv_conf::parse("nameserver 192.0.2.53\nsearch example.com"),
+            &args,
+        );
+        assert!(!config.recursion_desired);
+        assert!(config.search.is_empty());
+        assert_eq!(
+            config.nameservers,
+            vec![
+                "[::1]:1053".parse::<SocketAddr>().unwrap(),
+                "192.0.2.1:5353".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.transport, Transport::Tcp);
+        assert!(!config.retry_truncated);
+        assert_eq!(config.timeout, Duration::from_secs(1));
+        assert_eq!(config.attempts, 5);
+        assert_eq!(config.strategy, Strategy::RoundRobin);
+        assert!(config.edns0);
     }
 
-    Ok(())
+    #[test]
+    fn test_zone_transfer() {
+        let args = parse(&["example.org", "axfr"]).unwrap();
+        assert_eq!(args.record_type, RecordType::AXFR);
+        assert_eq!(
+            parse(&["example.org", "IXFR"]).unwrap_err(),
+            "IXFR is not supported, transfer the whole zone with AXFR"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["@not-an-ip", "google.com"]).is_err());
+        assert!(parse(&["google.com", "+tcpx"]).is_err());
+        assert!(parse(&["google.com", "A", "IN", "extra"]).is_err());
+        assert!(parse(&["-p"]).is_err());
+        assert!(parse(&["google.com", "--resolv-conf"]).is_err());
+        assert!(parse(&["google.com", "--hosts"]).is_err());
+        assert!(parse(&["google.com", "+timeout=soon"]).is_err());
+        assert!(parse(&["google.com", "+tries=-1"]).is_err());
+    }
 }
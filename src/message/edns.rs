@@ -0,0 +1,218 @@
+use bitvec::prelude::*;
+use nom::{
+    combinator::{map, verify},
+    multi::{length_data, many0},
+    number::complete::{be_u16, be_u32},
+    sequence::tuple,
+    IResult,
+};
+
+use super::{message::MAX_UDP_BYTES, name::parse_name};
+use crate::error::DnsError;
+
+/// The record type of the OPT pseudo-record (RFC 6891 6.1.1).
+pub const OPT: u16 = 41;
+
+/// The UDP payload size advertised by default, small enough to avoid IP
+/// fragmentation on most paths (DNS flag day 2020).
+pub const DEFAULT_PAYLOAD_SIZE: u16 = 1232;
+
+/// The DO bit in the TTL field of the OPT record (RFC 3225 3).
+const DNSSEC_OK: u32 = 1 << 15;
+
+/// A single option in the RDATA of the OPT record (RFC 6891 6.1.2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+/// The EDNS(0) parameters of a message, carried by the OPT pseudo-record of
+/// its additional section (RFC 6891).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    /// The largest UDP payload the sender can reassemble.
+    pub payload_size: u16,
+    /// The upper 8 bits of the 12 bit RCODE, whose lower 4 bits are in the header.
+    pub extended_rcode: u8,
+    pub version: u8,
+    /// Whether the sender can handle DNSSEC records (RFC 3225).
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Edns {
+    /// Version 0, advertising `payload_size`, without any option.
+    pub fn new(payload_size: u16) -> Self {
+        Self {
+            payload_size,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+
+    /// The largest UDP message the sender accepts. Sizes below 512 are
+    /// treated as 512 (RFC 6891 6.2.5).
+    pub fn max_payload(&self) -> usize {
+        usize::from(self.payload_size).max(MAX_UDP_BYTES)
+    }
+
+    /// Append the OPT record to `bv`. Its owner is the root, which is never compressed.
+    pub fn write(&self, bv: &mut BitVec<usize, Msb0>) -> Result<(), DnsError> {
+        bv.extend_from_bitslice(0u8.view_bits::<Msb0>());
+        bv.extend_from_bitslice(OPT.view_bits::<Msb0>());
+        bv.extend_from_bitslice(self.payload_size.view_bits::<Msb0>());
+        let mut ttl = u32::from(self.extended_rcode) << 24 | u32::from(self.version) << 16;
+        if self.dnssec_ok {
+            ttl |= DNSSEC_OK;
+        }
+        bv.extend_from_bitslice(ttl.view_bits::<Msb0>());
+
+        let rdlength: usize = self.options.iter().map(|o| 4 + o.data.len()).sum();
+        let rdlength = u16::try_from(rdlength).map_err(|_| DnsError::RDataTooLong(rdlength))?;
+        bv.extend_from_bitslice(rdlength.view_bits::<Msb0>());
+        for option in &self.options {
+            bv.extend_from_bitslice(option.code.view_bits::<Msb0>());
+            // fits, since the whole RDATA does
+            bv.extend_from_bitslice((option.data.len() as u16).view_bits::<Msb0>());
+            option
+                .data
+                .iter()
+                .for_each(|byte| bv.extend_from_bitslice(byte.view_bits::<Msb0>()));
+        }
+        Ok(())
+    }
+
+    /// Parse an OPT record from `i`, where `msg` is the whole message it was
+    /// taken from. Fails with a recoverable error when the record at `i` is
+    /// not an OPT record, so that it can be parsed as a regular one.
+    pub fn deserialize<'a>(msg: &'a [u8], i: &'a [u8]) -> IResult<&'a [u8], Self, DnsError> {
+        let (i, labels) = parse_name(msg, i)?;
+        let (i, _) = verify(be_u16, |record_type| *record_type == OPT)(i)?;
+        if labels != [""] {
+            return Err(nom::Err::Failure(DnsError::BadOpt(
+                "is not owned by the root",
+            )));
+        }
+        let (i, (payload_size, ttl, rdata)) = tuple((be_u16, be_u32, length_data(be_u16)))(i)?;
+        let (rest, options) = many0(map(
+            tuple((be_u16, length_data(be_u16))),
+            |(code, data): (u16, &[u8])| EdnsOption {
+                code,
+                data: data.to_vec(),
+            },
+        ))(rdata)?;
+        if !rest.is_empty() {
+            return Err(nom::Err::Failure(DnsError::TrailingGarbage(rest.len())));
+        }
+        let edns = Self {
+            payload_size,
+            extended_rcode: (ttl >> 24) as u8,
+            version: (ttl >> 16) as u8,
+            dnssec_ok: ttl & DNSSEC_OK != 0,
+            options,
+        };
+        Ok((i, edns))
+    }
+}
+
+impl Default for Edns {
+    fn default() -> Self {
+        Self::new(DEFAULT_PAYLOAD_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests_edns {
+    use super::*;
+    use std::io::Read;
+
+    fn bytes(edns: &Edns) -> Vec<u8> {
+        let mut bv = BitVec::<usize, Msb0>::new();
+        edns.write(&mut bv).unwrap();
+        let mut bytes = Vec::new();
+        bv.read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_write() {
+        assert_eq!(
+            bytes(&Edns::default()),
+            vec![0, 0, 41, 0x04, 0xd0, 0, 0, 0, 0, 0, 0]
+        );
+        let edns = Edns {
+            payload_size: 4096,
+            extended_rcode: 1,
+            version: 0,
+            dnssec_ok: true,
+            options: vec![EdnsOption {
+                code: 10,
+                data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            }],
+        };
+        assert_eq!(
+            bytes(&edns),
+            vec![
+                0, 0, 41, 0x10, 0, // root, OPT, payload size
+                1, 0, 0x80, 0, // extended RCODE, version, DO
+                0, 12, 0, 10, 0, 8, 1, 2, 3, 4, 5, 6, 7, 8, // a cookie
+            ]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let edns = Edns {
+            payload_size: 1400,
+            extended_rcode: 0xfe,
+            version: 1,
+            dnssec_ok: true,
+            options: vec![
+                EdnsOption {
+                    code: 3,
+                    data: Vec::new(),
+                },
+                EdnsOption {
+                    code: 65001,
+                    data: vec![0xff; 3],
+                },
+            ],
+        };
+        let bytes = bytes(&edns);
+        let (rest, parsed) = Edns::deserialize(&bytes, &bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed, edns);
+    }
+
+    #[test]
+    fn test_not_opt() {
+        let msg: &[u8] = &[0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0];
+        assert!(matches!(
+            Edns::deserialize(msg, msg),
+            Err(nom::Err::Error(_))
+        ));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let msg: &[u8] = &[1, b'a', 0, 0, 41, 2, 0, 0, 0, 0, 0, 0, 0];
+        let err = Edns::deserialize(msg, msg).unwrap_err();
+        assert_eq!(
+            DnsError::from(err),
+            DnsError::BadOpt("is not owned by the root")
+        );
+        // an option longer than the RDATA
+        let msg: &[u8] = &[0, 0, 41, 2, 0, 0, 0, 0, 0, 0, 4, 0, 10, 0, 8];
+        let err = Edns::deserialize(msg, msg).unwrap_err();
+        assert_eq!(DnsError::from(err), DnsError::Truncated);
+    }
+
+    #[test]
+    fn test_max_payload() {
+        assert_eq!(Edns::new(4096).max_payload(), 4096);
+        assert_eq!(Edns::new(0).max_payload(), 512);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::DnsError;
 use crate::message::{
     opcode::Opcode,
     parser::{take_bit, take_nibble, take_u16},
@@ -7,27 +8,32 @@ use log::debug;
 use bitvec::prelude::*;
 use nom::IResult;
 
-use super::{parser::BitInput, response_code::ResponseCode};
+use super::{
+    parser::BitInput,
+    response_code::{ResponseCode, MAX_HEADER_RESPONSE_CODE},
+};
 
 /// RFC 1035 defines DNS headers as 12 bytes long.
 const EXPECTED_HEADER_SIZE: usize = 12;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct MessageHeader {
     /// A 16 bit identifier assigned by the program that generates any kind of query.  This identifier is copied the corresponding reply and can be used by the requester to match up replies to outstanding queries.
     pub id: u16,
-    /// A one bit field that specifies whether this message is a query (0), or a response (1).
-    is_query: bool,
+    /// The QR bit: a one bit field that specifies whether this message is a query (0), or a response (1).
+    pub is_response: bool,
     /// A four bit field that specifies kind of query in this message.  This value is set by the originator of a query and copied into the response.
-    opcode: Opcode,
+    pub opcode: Opcode,
     /// This bit is valid in responses, and specifies that the responding name server is an authority for the domain name in question section. Note that the contents of the answer section may have multiple owner names because of aliases. The AA bit corresponds to the name which matches the query name, or the first owner name in the answer section.
-    authoritative_answer: bool,
+    pub authoritative_answer: bool,
     /// Specifies that this message was truncated due to length greater than that permitted on the transmission channel.
-    truncation: bool,
+    pub truncation: bool,
     /// This bit may be set in a query and is copied into the response.  If RD is set, it directs the name server to pursue the query recursively. Recursive query support is optional.
-    recursion_desired: bool,
+    pub recursion_desired: bool,
     /// This be (sic) is set or cleared in a response, and denotes whether recursive query support is available in the name server.
-    recursion_available: bool,
+    pub recursion_available: bool,
+    /// The lower 4 bits of the response code. Use [`Message::response_code`](super::message::Message::response_code)
+    /// for the whole code, whose upper bits are in the OPT record.
     pub resp_code: ResponseCode,
     /// Number of entries in the question section.
     pub question_count: u16,
@@ -42,7 +48,7 @@ impl MessageHeader {
     pub fn new(id: u16) -> Self {
         Self {
             id,
-            is_query: false, // QR : query (0), or a response (1)
+            is_response: false, // QR : query (0), or a response (1)
             opcode: Opcode::Query,
             authoritative_answer: false,
             truncation: false,
@@ -58,13 +64,18 @@ impl MessageHeader {
     }
 }
 
-impl<'a> MessageHeader {
-    pub fn as_bitvec(self) -> BitVec<usize, Msb0> {
+impl MessageHeader {
+    /// Serialize the header. Fails when the opcode is not valid, or the
+    /// response code does not fit in the header, see
+    /// [`Message::set_response_code`](super::message::Message::set_response_code).
+    pub fn as_bitvec(&self) -> Result<BitVec<usize, Msb0>, DnsError> {
+        self.opcode.check()?;
+        self.resp_code.check(MAX_HEADER_RESPONSE_CODE)?;
         debug!("expected header size {}", 8 * EXPECTED_HEADER_SIZE);
         let mut bv = BitVec::<usize, Msb0>::with_capacity(8 * EXPECTED_HEADER_SIZE);
 
         bv.extend_from_bitslice(self.id.view_bits::<Msb0>());
-        bv.push(self.is_query);
+        bv.push(self.is_response);
         bv.extend_from_bitslice(self.opcode.as_bitvec().as_bitslice());
 
         bv.push(self.authoritative_answer);
@@ -83,36 +94,39 @@ impl<'a> MessageHeader {
         bv.extend_from_bitslice(self.name_server_count.view_bits::<Msb0>());
         bv.extend_from_bitslice(self.additional_records_count.view_bits::<Msb0>());
         // assert_eq!(bv.len(), 8 * EXPECTED_HEADER_SIZE);
-        bv
+        Ok(bv)
     }
 }
 
 impl MessageHeader {
-    pub fn deserialize(i: BitInput) -> IResult<(&[u8], usize), Self> {
-        use nom::combinator::map_res;
-        
-        let (i, id) = take_u16(i).expect("id");
-        let (i, qr) = take_bit(i).expect("qr");
-
-        let (i, opcode) = map_res(take_nibble, Opcode::try_from)(i).expect("opcode");
-        let (i, aa) = take_bit(i).unwrap();
-        let (i, tc) = take_bit(i).unwrap();
-        let (i, rd) = take_bit(i).unwrap();
-        let (mut i, ra) = take_bit(i).unwrap();
+    pub fn deserialize(i: BitInput<'_>) -> IResult<BitInput<'_>, Self, DnsError> {
+        use nom::combinator::map;
+
+        let (i, id) = take_u16(i)?;
+        let (i, qr) = take_bit(i)?;
+
+        let (i, opcode) = map(take_nibble, Opcode::from)(i)?;
+        let (i, aa) = take_bit(i)?;
+        let (i, tc) = take_bit(i)?;
+        let (i, rd) = take_bit(i)?;
+        let (mut i, ra) = take_bit(i)?;
         for _ in 0..3 {
             let z;
-            (i, z) = take_bit(i).unwrap();
-            assert!(!z);
+            (i, z) = take_bit(i)?;
+            // Must be zero, but later RFCs give meaning to these bits: ignore them.
+            if z {
+                debug!("Ignoring reserved Z bit set in message {}", id);
+            }
         }
-        let (i, rcode) = map_res(take_nibble, ResponseCode::try_from)(i).unwrap();
-        let (i, qdcount) = take_u16(i).unwrap();
-        let (i, ancount) = take_u16(i).unwrap();
-        let (i, nscount) = take_u16(i).unwrap();
-        let (i, arcount) = take_u16(i).unwrap();
+        let (i, rcode) = map(take_nibble, |rcode| ResponseCode::from(u16::from(rcode)))(i)?;
+        let (i, qdcount) = take_u16(i)?;
+        let (i, ancount) = take_u16(i)?;
+        let (i, nscount) = take_u16(i)?;
+        let (i, arcount) = take_u16(i)?;
 
         let header = MessageHeader {
             id,
-            is_query: qr,
+            is_response: qr,
             opcode,
             authoritative_answer: aa,
             truncation: tc,
@@ -129,22 +143,17 @@ impl MessageHeader {
 }
 
 impl TryFrom<Vec<u8>> for MessageHeader {
-    type Error = std::io::Error;
+    type Error = DnsError;
 
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
         // Convert the Vec<u8> into a &[u8] for parsing
         let input = &value[..];
 
         // Here we explicitly annotate the error type to resolve conflicts
-        let result: IResult<&[u8], MessageHeader> = nom::bits(MessageHeader::deserialize)(input);
-
-        match result {
-            Ok((_, header)) => Ok(header),
-            Err(_) => Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Parsing failed",
-            )),
-        }
+        let result: IResult<&[u8], MessageHeader, DnsError> =
+            nom::bits(MessageHeader::deserialize)(input);
+
+        Ok(result?.1)
     }
 }
 
@@ -156,12 +165,12 @@ mod tests_header {
     #[test]
     fn test_bitvec() {
         let header = MessageHeader::new(1);
-        let bv = header.as_bitvec();
+        let bv = header.as_bitvec().unwrap();
         assert_eq!(bv.len(), 8 * EXPECTED_HEADER_SIZE);
 
         let mut expected = bitvec![usize, Msb0;];
         //
-        expected.extend_from_bitslice((1 as u16).view_bits::<Msb0>());
+        expected.extend_from_bitslice(1u16.view_bits::<Msb0>());
         expected.push(false); // query (0), or a response (1)
         expected.extend_from_bitslice(Opcode::Query.as_bitvec().as_bitslice());
         //ok
@@ -174,15 +183,73 @@ mod tests_header {
         expected.extend_from_bitslice(bits![0; 3]);
         //
         expected.extend_from_bitslice(ResponseCode::NoError.as_bitvec().as_bitslice());
-        expected.extend_from_bitslice((1 as u16).view_bits::<Msb0>());
-        expected.extend_from_bitslice((0 as u16).view_bits::<Msb0>());
-        expected.extend_from_bitslice((0 as u16).view_bits::<Msb0>());
-        expected.extend_from_bitslice((0 as u16).view_bits::<Msb0>());
+        expected.extend_from_bitslice(1u16.view_bits::<Msb0>());
+        expected.extend_from_bitslice(0u16.view_bits::<Msb0>());
+        expected.extend_from_bitslice(0u16.view_bits::<Msb0>());
+        expected.extend_from_bitslice(0u16.view_bits::<Msb0>());
 
         // assert_eq!(expected.len(), 8 * EXPECTED_HEADER_SIZE);
         assert_eq!(bv, expected);
     }
 
+    #[test]
+    fn test_try_from() {
+        let mut bytes = vec![0, 42, 0b1000_0101, 0b1000_0011, 0, 1, 0, 2, 0, 0, 0, 0];
+        let header = MessageHeader::try_from(bytes.clone()).unwrap();
+        assert_eq!(header.id, 42);
+        assert!(header.is_response);
+        assert!(header.authoritative_answer);
+        assert!(header.recursion_desired);
+        assert!(header.recursion_available);
+        assert_eq!(header.question_count, 1);
+        assert_eq!(header.answer_count, 2);
+
+        // reserved Z bits are tolerated
+        bytes[3] |= 0b0111_0000;
+        assert!(MessageHeader::try_from(bytes.clone()).is_ok());
+
+        assert_eq!(
+            MessageHeader::try_from(bytes[..5].to_vec()).unwrap_err(),
+            DnsError::Truncated
+        );
+
+        bytes[2] = 0b0111_1000;
+        assert_eq!(
+            MessageHeader::try_from(bytes.clone()).unwrap().opcode,
+            Opcode::Unknown(15)
+        );
+        bytes[2] = 0b0010_1000;
+        let header = MessageHeader::try_from(bytes).unwrap();
+        assert_eq!(header.opcode, Opcode::Update);
+        assert_eq!(
+            header.as_bitvec().unwrap()[17..21],
+            bits![usize, Msb0; 0, 1, 0, 1]
+        );
+    }
+
+    #[test]
+    fn test_bad_response_code() {
+        let mut header = MessageHeader::new(1);
+        for resp_code in [ResponseCode::BadCookie, ResponseCode::Unknown(5)] {
+            header.resp_code = resp_code;
+            assert_eq!(
+                header.as_bitvec().unwrap_err(),
+                DnsError::BadResponseCode(resp_code.into())
+            );
+        }
+        header.resp_code = ResponseCode::Unknown(15);
+        assert!(header.as_bitvec().is_ok());
+    }
+
+    #[test]
+    fn test_bad_opcode() {
+        let mut header = MessageHeader::new(1);
+        header.opcode = Opcode::Unknown(16);
+        assert_eq!(header.as_bitvec().unwrap_err(), DnsError::BadOpcode(16));
+        header.opcode = Opcode::Unknown(0);
+        assert_eq!(header.as_bitvec().unwrap_err(), DnsError::BadOpcode(0));
+    }
+
     #[test]
     fn test_bitvec_x() {
         // let mut bitvec: BitVec<usize, Msb0> = BitVec::new();
@@ -194,7 +261,7 @@ mod tests_header {
         let buffer_u8: &[u8] = &[16, 0, 1];
         // let bvu_ = bitvec![u8, Msb0;0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
 
-        let buffer_u16 = buffer_u8.into_iter().map(|x| u16::from(*x));
+        let buffer_u16 = buffer_u8.iter().map(|x| u16::from(*x));
         let bitvec: BitVec<u16, Msb0> = BitVec::from_iter(buffer_u16);
         assert_eq!(16, bitvec[0..16].load::<u16>());
         assert_eq!(0, bitvec[16..32].load::<u16>());
@@ -1,31 +1,37 @@
 use log::debug;
 use nom::IResult;
-use std::io::Read;
 
-use crate::rr::{record_class::Class, record_type::RecordType};
+use crate::error::DnsError;
+use crate::rr::{record::Record, record_class::Class, record_type::RecordType};
 use bitvec::prelude::*;
 
 /// Defined by the spec
 /// UDP messages    512 octets or less
-pub(crate) const MAX_UDP_BYTES: usize = 512;
+pub const MAX_UDP_BYTES: usize = 512;
 
 /// Defined by the spec
 /// labels          63 octets or less
-const MAX_LABEL_BYTES: usize = 63;
+pub(crate) const MAX_LABEL_BYTES: usize = 63;
 
 /// Defined by the spec
 /// names           255 octets or less
-const MAX_NAME_BYTES: usize = 255;
+pub(crate) const MAX_NAME_BYTES: usize = 255;
 
-use super::{header::MessageHeader, parser::BitInput, question::Question};
+/// The size of a record with the root name and no RDATA, which no record is
+/// shorter than. The counts of the header are capped with it, as they cannot
+/// be trusted before parsing.
+const MIN_RECORD_BYTES: usize = 11;
 
-pub struct Record {
-    pub name: String,
-    pub class: Class,
-}
+use super::{
+    edns::Edns,
+    header::MessageHeader,
+    name::NameCompressor,
+    question::Question,
+    response_code::{ResponseCode, MAX_RESPONSE_CODE},
+};
 
-#[derive(Debug, Clone)]
-pub struct Message<'a> {
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
     /// The header section is always present.  The header includes fields that
     /// specify which of the remaining sections are present, and also specify
     /// whether the message is a query or a response, a standard query or some
@@ -34,94 +40,261 @@ pub struct Message<'a> {
     // The question section contains fields that describe a
     // question to a name server.  These fields are a query type (QTYPE), a
     // query class (QCLASS), and a query domain name (QNAME).
-    pub question: Vec<Question<'a>>,
+    pub question: Vec<Question>,
+    /// Resource records that answer the question.
+    pub answer: Vec<Record>,
+    /// Resource records that point toward an authoritative name server.
+    pub authority: Vec<Record>,
+    /// Resource records that relate to the query, but are not strictly answers for the question.
+    pub additional: Vec<Record>,
+    /// The EDNS(0) parameters, from the OPT pseudo-record of the additional
+    /// section, which is not part of `additional`.
+    pub edns: Option<Edns>,
 }
 
-impl<'a> Message<'a> {
+impl Message {
     pub fn new(
         id: u16,
-        domain_name: &'a str,
+        domain_name: &str,
         record_type: RecordType,
         record_class: Class,
-    ) -> Result<Self, anyhow::Error> {
+    ) -> Result<Self, DnsError> {
         let name_len = domain_name.len();
         if name_len > MAX_NAME_BYTES {
-            anyhow::bail!(
-                "Domain name is {name_len} bytes, which is over the max of {MAX_NAME_BYTES}"
-            );
+            return Err(DnsError::NameTooLong(name_len));
         }
         let labels: Vec<&str> = domain_name.split('.').collect();
-        if labels.iter().any(|label| label.len() > MAX_LABEL_BYTES) {
-            anyhow::bail!(
-                "One of the labels in your domain is over the max of {MAX_LABEL_BYTES} bytes"
-            );
+        if let Some(label) = labels.iter().find(|label| label.len() > MAX_LABEL_BYTES) {
+            return Err(DnsError::BadLabelLength(label.len()));
         }
         debug!("labels : {:?}", labels);
 
         let ret = Message {
             header: MessageHeader::new(id),
-            question: vec![Question::new(labels, record_type, record_class)],
+            question: vec![Question::new(domain_name, record_type, record_class)],
+            answer: Vec::new(),
+            authority: Vec::new(),
+            additional: Vec::new(),
+            edns: None,
         };
         Ok(ret)
     }
 
-    pub fn as_bitvec(self) -> Result<BitVec<usize, Msb0>, std::io::Error> {
+    /// Serialize the message, compressing repeated names.
+    pub fn as_bitvec(&self) -> Result<BitVec<usize, Msb0>, DnsError> {
+        self.as_bitvec_with(true)
+    }
+
+    /// Serialize the message, writing every name in full when `compress` is false.
+    /// The section counts of the header are taken from the sections themselves.
+    pub fn as_bitvec_with(&self, compress: bool) -> Result<BitVec<usize, Msb0>, DnsError> {
         let mut bv = BitVec::<usize, Msb0>::new();
+        let mut names = NameCompressor::new(compress);
 
-        bv.extend_from_bitslice(self.header.as_bitvec().as_bitslice());
+        let count = |len: usize| u16::try_from(len).map_err(|_| DnsError::TooManyRecords(len));
+        let mut header = self.header;
+        header.question_count = count(self.question.len())?;
+        header.answer_count = count(self.answer.len())?;
+        header.name_server_count = count(self.authority.len())?;
+        header.additional_records_count =
+            count(self.additional.len() + usize::from(self.edns.is_some()))?;
+        bv.extend_from_bitslice(header.as_bitvec()?.as_bitslice());
 
-        for q in self.question {
+        for q in &self.question {
             debug!("Serializing question {:?}", q);
-            match q.as_bitvec() {
-                Ok(bv_question) => {
-                    bv.extend_from_bitslice(bv_question.as_bitslice());
-                }
-                Err(e) => return Err(e),
-            }
+            q.write(&mut bv, &mut names)?;
+        }
+
+        for record in self
+            .answer
+            .iter()
+            .chain(&self.authority)
+            .chain(&self.additional)
+        {
+            debug!("Serializing record {:?}", record);
+            record.write(&mut bv, &mut names)?;
+        }
+        if let Some(edns) = &self.edns {
+            edns.write(&mut bv)?;
         }
 
         Ok(bv)
     }
 
-    pub fn as_vec(self) -> Vec<u8> {
+    pub fn as_vec(&self) -> Result<Vec<u8>, DnsError> {
+        self.as_vec_with(true)
+    }
+
+    /// Serialize the message to bytes, writing every name in full when `compress` is false.
+    pub fn as_vec_with(&self, compress: bool) -> Result<Vec<u8>, DnsError> {
         debug!("Serializing Message {:?}", self);
-        let mut bv: BitVec<usize, Msb0> = self.as_bitvec().expect("Could not serialize");
+        let bv: BitVec<usize, Msb0> = self.as_bitvec_with(compress)?;
         let mut msg_bytes = Vec::with_capacity(MAX_UDP_BYTES);
-        bv.read_to_end(&mut msg_bytes).unwrap();
-        msg_bytes
-    }
-
-    pub fn deserialize(i: BitInput<'a>) -> IResult<(&'a [u8], usize), Self> {
-        let i = nom::bits::bits::<
-            &[u8],
-            MessageHeader,
-            nom::error::Error<(&[u8], usize)>,
-            nom::error::Error<_>,
-            _,
-        >(MessageHeader::deserialize)(i.0)
-        .unwrap();
-        let header = i.1;
-
-        let i = nom::bits::bits::<
-            &[u8],
-            Question,
-            nom::error::Error<(&[u8], usize)>,
-            nom::error::Error<_>,
-            _,
-        >(Question::deserialize)(i.0)
-        .unwrap();
-        let question = i.1;
-
-        let mut questions = Vec::new();
-        questions.push(question);
-
-        return Ok((
-            (i.0, i.0.len()),
-            Self {
-                header,
-                question: questions,
-            },
-        ));
+        msg_bytes.extend(bv.chunks(8).map(|byte| byte.load_be::<u8>()));
+        Ok(msg_bytes)
+    }
+
+    /// Parse a whole message. Every byte of `i` must belong to the message.
+    pub fn deserialize(i: &[u8]) -> Result<Self, DnsError> {
+        // Compression pointers are offsets from the start of the message.
+        let msg = i;
+        let (mut rest, header) =
+            nom::bits::bits::<_, _, DnsError, DnsError, _>(MessageHeader::deserialize)(msg)?;
+
+        let mut questions = Vec::with_capacity(header.question_count.into());
+        for _ in 0..header.question_count {
+            let question;
+            (rest, question) =
+                nom::bits::bits::<_, _, DnsError, DnsError, _>(|i| Question::deserialize(msg, i))(
+                    rest,
+                )?;
+            questions.push(question);
+        }
+
+        let answer;
+        (rest, answer) = Self::deserialize_records(msg, rest, header.answer_count)?;
+        let authority;
+        (rest, authority) = Self::deserialize_records(msg, rest, header.name_server_count)?;
+        let mut additional = Vec::with_capacity(
+            usize::from(header.additional_records_count).min(rest.len() / MIN_RECORD_BYTES),
+        );
+        let mut edns = None;
+        for _ in 0..header.additional_records_count {
+            match Edns::deserialize(msg, rest) {
+                Ok((_, _)) if edns.is_some() => {
+                    return Err(DnsError::BadOpt("appears more than once"));
+                }
+                Ok((after, opt)) => {
+                    rest = after;
+                    edns = Some(opt);
+                }
+                Err(nom::Err::Error(_)) => {
+                    let record;
+                    (rest, record) = Record::deserialize(msg, rest)?;
+                    additional.push(record);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if !rest.is_empty() {
+            return Err(DnsError::TrailingGarbage(rest.len()));
+        }
+
+        Ok(Self {
+            header,
+            question: questions,
+            answer,
+            authority,
+            additional,
+            edns,
+        })
+    }
+
+    /// Parse `count` consecutive resource records.
+    fn deserialize_records<'a>(
+        msg: &'a [u8],
+        mut i: &'a [u8],
+        count: u16,
+    ) -> IResult<&'a [u8], Vec<Record>, DnsError> {
+        let mut records = Vec::with_capacity(usize::from(count).min(i.len() / MIN_RECORD_BYTES));
+        for _ in 0..count {
+            let record;
+            (i, record) = Record::deserialize(msg, i)?;
+            records.push(record);
+        }
+        Ok((i, records))
+    }
+
+    /// Replace the answer section, keeping the header count in step.
+    pub fn set_answer(&mut self, answer: Vec<Record>) {
+        self.answer = answer;
+        self.update_counts();
+    }
+
+    /// Replace the authority section, keeping the header count in step.
+    pub fn set_authority(&mut self, authority: Vec<Record>) {
+        self.authority = authority;
+        self.update_counts();
+    }
+
+    /// Replace the additional section, keeping the header count in step.
+    pub fn set_additional(&mut self, additional: Vec<Record>) {
+        self.additional = additional;
+        self.update_counts();
+    }
+
+    /// Set or remove the OPT record, which is counted in the additional section.
+    pub fn set_edns(&mut self, edns: Option<Edns>) {
+        self.edns = edns;
+        self.update_counts();
+    }
+
+    /// Set the section counts of the header from the sections. Counts over the
+    /// max are left for serializing to report.
+    pub(crate) fn update_counts(&mut self) {
+        let count = |len: usize| u16::try_from(len).unwrap_or(u16::MAX);
+        self.header.question_count = count(self.question.len());
+        self.header.answer_count = count(self.answer.len());
+        self.header.name_server_count = count(self.authority.len());
+        self.header.additional_records_count =
+            count(self.additional.len() + usize::from(self.edns.is_some()));
+    }
+
+    /// The response code, made of the 4 bits of the header and the 8 upper
+    /// bits of the OPT record when there is one (RFC 6891 6.1.3).
+    pub fn response_code(&self) -> ResponseCode {
+        let extended = self.edns.as_ref().map_or(0, |edns| edns.extended_rcode);
+        ResponseCode::from_parts(self.header.resp_code.header_bits(), extended)
+    }
+
+    /// Set the response code, adding an OPT record for its upper bits when it
+    /// does not fit in the header. Fails for codes over 12 bits, or
+    /// unassigned codes standing for assigned ones.
+    pub fn set_response_code(&mut self, resp_code: ResponseCode) -> Result<(), DnsError> {
+        resp_code.check(MAX_RESPONSE_CODE)?;
+        self.header.resp_code = ResponseCode::from_parts(resp_code.header_bits(), 0);
+        if resp_code.extended_bits() != 0 {
+            self.edns.get_or_insert_with(Edns::default);
+        }
+        if let Some(edns) = &mut self.edns {
+            edns.extended_rcode = resp_code.extended_bits();
+        }
+        self.update_counts();
+        Ok(())
+    }
+
+    /// Whether this message is a response to `query`: same id, QR bit set and
+    /// the same questions. Anything else is a stray or spoofed packet. Errors
+    /// may come without the questions, as servers often send them.
+    pub fn is_response_to(&self, query: &Message) -> bool {
+        if self.header.id != query.header.id {
+            debug!(
+                "Response id {} does not match query id {}",
+                self.header.id, query.header.id
+            );
+            return false;
+        }
+        if !self.header.is_response {
+            debug!("Response {} does not have the QR bit set", self.header.id);
+            return false;
+        }
+        if self.question.is_empty() && self.response_code() != ResponseCode::NoError {
+            return true;
+        }
+        let same_questions = self.question.len() == query.question.len()
+            && self
+                .question
+                .iter()
+                .zip(&query.question)
+                .all(|(r, q)| r.matches(q));
+        if !same_questions {
+            debug!(
+                "Response {} is for questions {:?}",
+                self.header.id, self.question
+            );
+        }
+        same_questions
     }
 
     // pub fn deserialize_x(i: &[u16]) -> IResult<(&[u16], usize), Self> {
@@ -143,3 +316,189 @@ impl<'a> Message<'a> {
     //     ));
     // }
 }
+
+#[cfg(test)]
+mod tests_message {
+    use super::*;
+    use crate::rr::rdata::RData;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_as_vec_compression() {
+        let mut message = Message::new(1, "google.com.", RecordType::A, Class::IN).unwrap();
+        let record = Record {
+            name: "google.com.".to_string(),
+            record_type: RecordType::A,
+            class: Class::IN,
+            ttl: 300,
+            rdata: RData::A(Ipv4Addr::LOCALHOST),
+        };
+        message.answer = vec![record.clone(), record];
+
+        let uncompressed = message.as_vec_with(false).unwrap();
+        let compressed = message.as_vec().unwrap();
+        // each answer name shrinks from 12 bytes to a 2 bytes pointer
+        assert_eq!(uncompressed.len(), compressed.len() + 2 * 10);
+        assert_eq!(compressed[12 + 16..12 + 16 + 2], [0xc0, 12]);
+        // answer count is taken from the section
+        assert_eq!(compressed[6..8], [0, 2]);
+    }
+
+    #[test]
+    fn test_deserialize_malformed() {
+        assert_eq!(
+            Message::deserialize(&[0, 1, 2]).unwrap_err(),
+            DnsError::Truncated
+        );
+
+        // a header announcing one answer, and nothing else
+        let header: &[u8] = &[0, 1, 0x81, 0x80, 0, 0, 0, 1, 0, 0, 0, 0];
+        assert_eq!(
+            Message::deserialize(header).unwrap_err(),
+            DnsError::Truncated
+        );
+
+        let mut garbage = header.to_vec();
+        garbage[7] = 0;
+        garbage.push(0);
+        assert_eq!(
+            Message::deserialize(&garbage).unwrap_err(),
+            DnsError::TrailingGarbage(1)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_never_panics() {
+        let mut message = Message::new(1, "www.google.com.", RecordType::MX, Class::IN).unwrap();
+        message.answer = vec![Record {
+            name: "google.com.".to_string(),
+            record_type: RecordType::MX,
+            class: Class::IN,
+            ttl: 300,
+            rdata: RData::MX {
+                preference: 10,
+                exchange: "smtp.google.com.".to_string(),
+            },
+        }];
+        let bytes = message.as_vec().unwrap();
+
+        for len in 0..bytes.len() {
+            let _ = Message::deserialize(&bytes[..len]);
+        }
+        for ix in 0..bytes.len() {
+            for value in [0x00, 0x3f, 0x40, 0xc0, 0xff] {
+                let mut mutated = bytes.clone();
+                mutated[ix] = value;
+                let _ = Message::deserialize(&mutated);
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_response_to() {
+        let query = Message::new(42, "google.com.", RecordType::A, Class::IN).unwrap();
+        let mut response = query.clone();
+        assert!(!response.is_response_to(&query), "QR bit is not set");
+        response.header.is_response = true;
+        assert!(response.is_response_to(&query));
+
+        response.question[0].name = "GOOGLE.com.".to_string();
+        assert!(response.is_response_to(&query));
+
+        let mut other = response.clone();
+        other.header.id = 43;
+        assert!(!other.is_response_to(&query));
+
+        let mut other = response.clone();
+        other.question[0].name = "google.org.".to_string();
+        assert!(!other.is_response_to(&query));
+
+        let mut other = response.clone();
+        other.question[0].record_type = RecordType::AAAA;
+        assert!(!other.is_response_to(&query));
+
+        let mut other = response.clone();
+        other.question.clear();
+        assert!(!other.is_response_to(&query));
+        // errors may leave the questions out
+        other.header.resp_code = ResponseCode::FormatError;
+        assert!(other.is_response_to(&query));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut message = Message::new(42, "google.com.", RecordType::MX, Class::IN).unwrap();
+        message.set_answer(vec![Record {
+            name: "google.com.".to_string(),
+            record_type: RecordType::MX,
+            class: Class::IN,
+            ttl: 300,
+            rdata: RData::MX {
+                preference: 10,
+                exchange: "smtp.google.com.".to_string(),
+            },
+        }]);
+        assert_eq!(message.header.answer_count, 1);
+        let bytes = message.as_vec().unwrap();
+        assert_eq!(Message::deserialize(&bytes).unwrap(), message);
+    }
+
+    #[test]
+    fn test_edns_round_trip() {
+        let mut message = Message::new(42, "google.com.", RecordType::A, Class::IN).unwrap();
+        message.set_additional(vec![Record {
+            name: "google.com.".to_string(),
+            record_type: RecordType::A,
+            class: Class::IN,
+            ttl: 300,
+            rdata: RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+        }]);
+        message.set_edns(Some(Edns::default()));
+        assert_eq!(message.header.additional_records_count, 2);
+        let bytes = message.as_vec().unwrap();
+        // the OPT record comes last
+        assert_eq!(
+            bytes[bytes.len() - 11..],
+            [0, 0, 41, 4, 0xd0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(Message::deserialize(&bytes).unwrap(), message);
+
+        // a second OPT record
+        let mut bytes = bytes;
+        bytes[11] = 3;
+        bytes.extend_from_slice(&[0, 0, 41, 2, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            Message::deserialize(&bytes),
+            Err(DnsError::BadOpt("appears more than once"))
+        );
+    }
+
+    #[test]
+    fn test_extended_response_code() {
+        let mut message = Message::new(42, "google.com.", RecordType::A, Class::IN).unwrap();
+        message.set_response_code(ResponseCode::NameError).unwrap();
+        assert_eq!(message.edns, None);
+        assert_eq!(message.response_code(), ResponseCode::NameError);
+
+        message.set_response_code(ResponseCode::BadCookie).unwrap();
+        assert_eq!(message.header.resp_code, ResponseCode::RRSetExists);
+        assert_eq!(
+            message.edns.as_ref().map(|edns| edns.extended_rcode),
+            Some(1)
+        );
+        let parsed = Message::deserialize(&message.as_vec().unwrap()).unwrap();
+        assert_eq!(parsed.response_code(), ResponseCode::BadCookie);
+
+        for resp_code in [ResponseCode::Unknown(4096), ResponseCode::Unknown(23)] {
+            assert_eq!(
+                message.set_response_code(resp_code),
+                Err(DnsError::BadResponseCode(resp_code.into()))
+            );
+        }
+        assert_eq!(message.response_code(), ResponseCode::BadCookie);
+
+        // without an OPT record, only the header bits are left
+        message.edns = None;
+        assert_eq!(message.response_code(), ResponseCode::RRSetExists);
+    }
+}
@@ -1,7 +1,10 @@
-pub mod message;
-pub mod question;
+pub mod edns;
 pub mod header;
+#[allow(clippy::module_inception)]
+pub mod message;
+pub mod name;
 pub mod opcode;
+pub mod question;
 pub mod response_code;
 
-pub mod parser;
\ No newline at end of file
+pub mod parser;
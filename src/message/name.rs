@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+
+use bitvec::prelude::*;
+use log::debug;
+use nom::{
+    number::complete::{be_u16, be_u8},
+    IResult,
+};
+
+use super::{
+    message::{MAX_LABEL_BYTES, MAX_NAME_BYTES},
+    question::Question,
+};
+use crate::error::DnsError;
+
+/// Upper bound on the number of compression pointers followed for a single name.
+/// A legitimate name never needs more than one pointer per label.
+const MAX_POINTER_HOPS: usize = 127;
+
+/// The two top bits of a length octet that mark a compression pointer (RFC 1035 4.1.4).
+const POINTER_MASK: u8 = 0b1100_0000;
+
+/// Pointers have 14 bits of offset, names written further in the message cannot be pointed to.
+const MAX_POINTER_OFFSET: usize = 0x3fff;
+
+/// Writes names into a message, replacing suffixes already written by
+/// compression pointers (RFC 1035 4.1.4).
+///
+/// The bit vector given to [`NameCompressor::write_name`] must start at the
+/// beginning of the message, since pointers are offsets from there.
+#[derive(Debug, Clone)]
+pub struct NameCompressor {
+    enabled: bool,
+    /// Offset of every name suffix written so far, keyed by its lowercase form.
+    offsets: HashMap<String, u16>,
+}
+
+impl NameCompressor {
+    /// A compressor that emits pointers when `enabled`, or writes every name
+    /// in full otherwise (e.g. for the canonical wire form used by DNSSEC).
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            offsets: HashMap::new(),
+        }
+    }
+
+    /// Write `labels` followed by the root label, or a pointer to a previous occurrence.
+    /// Empty labels are skipped, so both `["com", ""]` and `["com"]` write `com.`.
+    pub fn write_name(
+        &mut self,
+        bv: &mut BitVec<usize, Msb0>,
+        labels: &[&str],
+    ) -> Result<(), DnsError> {
+        let labels: Vec<&str> = labels.iter().copied().filter(|l| !l.is_empty()).collect();
+        let name_len: usize = labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1;
+        if name_len > MAX_NAME_BYTES {
+            return Err(DnsError::NameTooLong(name_len));
+        }
+
+        for (ix, label) in labels.iter().enumerate() {
+            let suffix = labels[ix..].join(".").to_lowercase();
+            if self.enabled {
+                if let Some(offset) = self.offsets.get(&suffix) {
+                    debug!("Compressing {} to pointer {}", suffix, offset);
+                    let pointer = u16::from(POINTER_MASK) << 8 | offset;
+                    bv.extend_from_bitslice(pointer.view_bits::<Msb0>());
+                    return Ok(());
+                }
+                let offset = bv.len() / 8;
+                if offset <= MAX_POINTER_OFFSET {
+                    self.offsets.insert(suffix, offset as u16);
+                }
+            }
+
+            debug!("Serializing label {:?}", label);
+            // The mapping of domain names to labels is defined in RFC 1035:
+            // 2.3.1. Preferred name syntax
+            if label.len() > MAX_LABEL_BYTES {
+                return Err(DnsError::BadLabelLength(label.len()));
+            }
+            bv.extend_from_bitslice((label.len() as u8).view_bits::<Msb0>());
+            label
+                .bytes()
+                .for_each(|byte| bv.extend_from_bitslice(byte.view_bits::<Msb0>()));
+        }
+        // the root label terminating the name
+        bv.extend_from_bitslice(0u8.view_bits::<Msb0>());
+        Ok(())
+    }
+}
+
+impl Default for NameCompressor {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+/// Parse a domain name starting at `i`, following compression pointers into `msg`.
+///
+/// `msg` must be the whole message `i` was taken from, since pointers are
+/// offsets from the start of the message. The returned input is positioned
+/// right after the name as it appears in `i`, i.e. after the first pointer
+/// when the name is compressed. Labels include the terminating empty label.
+pub fn parse_name<'a>(msg: &'a [u8], i: &'a [u8]) -> IResult<&'a [u8], Vec<&'a str>, DnsError> {
+    let mut labels = Vec::new();
+    let mut visited: Vec<usize> = Vec::new();
+    // Where parsing resumes once the name is read: set when the first pointer is followed.
+    let mut rest: Option<&'a [u8]> = None;
+    let mut name_len = 0;
+    let mut ix = i;
+
+    loop {
+        let (_, len) = be_u8(ix)?;
+        match len & POINTER_MASK {
+            POINTER_MASK => {
+                let (after, pointer) = be_u16(ix)?;
+                let offset = usize::from(pointer & !(u16::from(POINTER_MASK) << 8));
+                debug!("Following compression pointer to {}", offset);
+                if visited.contains(&offset) {
+                    return Err(nom::Err::Failure(DnsError::PointerLoop(offset)));
+                }
+                if visited.len() >= MAX_POINTER_HOPS {
+                    return Err(nom::Err::Failure(DnsError::TooManyPointers));
+                }
+                if offset >= msg.len() {
+                    return Err(nom::Err::Failure(DnsError::BadPointer(offset)));
+                }
+                visited.push(offset);
+                rest.get_or_insert(after);
+                ix = &msg[offset..];
+            }
+            0 => {
+                let (after, label) = Question::parse_label(ix)?;
+                name_len += label.len() + 1;
+                if name_len > MAX_NAME_BYTES {
+                    return Err(nom::Err::Failure(DnsError::NameTooLong(name_len)));
+                }
+                labels.push(label);
+                if label.is_empty() {
+                    return Ok((rest.unwrap_or(after), labels));
+                }
+                ix = after;
+            }
+            // 01 and 10 are reserved for future use
+            _ => return Err(nom::Err::Failure(DnsError::BadLabelLength(len.into()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_name {
+    use super::*;
+    use std::io::Read;
+
+    fn write_names(compressor: &mut NameCompressor, names: &[&[&str]]) -> Vec<u8> {
+        let mut bv = BitVec::<usize, Msb0>::new();
+        for labels in names {
+            compressor.write_name(&mut bv, labels).unwrap();
+        }
+        let mut bytes = Vec::new();
+        bv.read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// `google.com.` at offset 0, followed by `www` and a pointer back to it.
+    const MESSAGE: &[u8] = &[
+        6, b'g', b'o', b'o', b'g', b'l', b'e', 3, b'c', b'o', b'm', 0, // offset 0
+        3, b'w', b'w', b'w', 0xc0, 0, // offset 12
+        0xc0, 7, // offset 18, points to `com.`
+        0xff,
+    ];
+
+    #[test]
+    fn test_uncompressed() {
+        let (rest, labels) = parse_name(MESSAGE, MESSAGE).unwrap();
+        assert_eq!(labels, vec!["google", "com", ""]);
+        assert_eq!(rest, &MESSAGE[12..]);
+    }
+
+    #[test]
+    fn test_pointer_after_labels() {
+        let (rest, labels) = parse_name(MESSAGE, &MESSAGE[12..]).unwrap();
+        assert_eq!(labels, vec!["www", "google", "com", ""]);
+        assert_eq!(rest, &MESSAGE[18..]);
+    }
+
+    #[test]
+    fn test_pointer_only() {
+        let (rest, labels) = parse_name(MESSAGE, &MESSAGE[18..]).unwrap();
+        assert_eq!(labels, vec!["com", ""]);
+        assert_eq!(rest, &[0xff]);
+    }
+
+    #[test]
+    fn test_pointer_loop() {
+        let msg: &[u8] = &[1, b'a', 0xc0, 0];
+        let err = parse_name(msg, msg).unwrap_err();
+        assert_eq!(DnsError::from(err), DnsError::PointerLoop(0));
+
+        let msg: &[u8] = &[0xc0, 0];
+        let err = parse_name(msg, msg).unwrap_err();
+        assert_eq!(DnsError::from(err), DnsError::PointerLoop(0));
+    }
+
+    #[test]
+    fn test_too_many_pointers() {
+        // a root label, then pointers each going to the previous one
+        let mut msg = vec![0, 0];
+        for target in (0..200u16).map(|ix| ix * 2) {
+            msg.extend_from_slice(&(0xc000 | target).to_be_bytes());
+        }
+        let err = parse_name(&msg, &msg[msg.len() - 2..]).unwrap_err();
+        assert_eq!(DnsError::from(err), DnsError::TooManyPointers);
+        // a shorter chain is fine
+        let (_, labels) = parse_name(&msg, &msg[20..]).unwrap();
+        assert_eq!(labels, vec![""]);
+    }
+
+    #[test]
+    fn test_pointer_out_of_bounds() {
+        let msg: &[u8] = &[0xc0, 42];
+        let err = parse_name(msg, msg).unwrap_err();
+        assert_eq!(DnsError::from(err), DnsError::BadPointer(42));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let msg: &[u8] = &[3, b'c', b'o'];
+        let err = parse_name(msg, msg).unwrap_err();
+        assert_eq!(DnsError::from(err), DnsError::Truncated);
+    }
+
+    #[test]
+    fn test_reserved_label_type() {
+        let msg: &[u8] = &[0x40, 0];
+        let err = parse_name(msg, msg).unwrap_err();
+        assert_eq!(DnsError::from(err), DnsError::BadLabelLength(0x40));
+        let msg: &[u8] = &[0x80, 0];
+        assert!(parse_name(msg, msg).is_err());
+    }
+
+    #[test]
+    fn test_write_compressed() {
+        let bytes = write_names(
+            &mut NameCompressor::default(),
+            &[&["google", "com", ""], &["www", "Google", "com"], &["com"]],
+        );
+        assert_eq!(bytes, &MESSAGE[..20]);
+    }
+
+    #[test]
+    fn test_write_uncompressed() {
+        let bytes = write_names(
+            &mut NameCompressor::new(false),
+            &[&["google", "com", ""], &["google", "com", ""]],
+        );
+        assert_eq!(bytes[..12], MESSAGE[..12]);
+        assert_eq!(bytes[12..], MESSAGE[..12]);
+    }
+
+    #[test]
+    fn test_write_root() {
+        let bytes = write_names(&mut NameCompressor::default(), &[&[""], &[]]);
+        assert_eq!(bytes, vec![0, 0]);
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let names: &[&[&str]] = &[&["a", "example", "org"], &["b", "example", "org"], &["org"]];
+        let bytes = write_names(&mut NameCompressor::default(), names);
+        let (i, first) = parse_name(&bytes, &bytes).unwrap();
+        let (i, second) = parse_name(&bytes, i).unwrap();
+        let (i, third) = parse_name(&bytes, i).unwrap();
+        assert!(i.is_empty());
+        assert_eq!(first, vec!["a", "example", "org", ""]);
+        assert_eq!(second, vec!["b", "example", "org", ""]);
+        assert_eq!(third, vec!["org", ""]);
+        // `b` plus a pointer, then a single pointer
+        assert_eq!(bytes.len(), 15 + 4 + 2);
+    }
+
+    #[test]
+    fn test_write_label_too_long() {
+        let label = "a".repeat(64);
+        let mut bv = BitVec::<usize, Msb0>::new();
+        assert!(NameCompressor::default()
+            .write_name(&mut bv, &[&label])
+            .is_err());
+    }
+
+    #[test]
+    fn test_name_too_long() {
+        // 5 labels of 63 bytes each is over the 255 bytes limit
+        let mut msg = Vec::new();
+        for _ in 0..5 {
+            msg.push(63);
+            msg.extend_from_slice(&[b'a'; 63]);
+        }
+        msg.push(0);
+        assert!(parse_name(&msg, &msg).is_err());
+    }
+}
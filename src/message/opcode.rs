@@ -1,6 +1,14 @@
 use bitvec::prelude::*;
 
-#[derive(Debug, Clone, Copy)]
+use crate::error::DnsError;
+
+/// The largest opcode, as they are 4 bits long.
+const MAX_OPCODE: u8 = 0xf;
+
+/// The kind of query of a message, from the IANA DNS OpCodes registry.
+///
+/// Opcodes are 4 bits long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Opcode {
     /// 0: a standard query (QUERY)
     Query,
@@ -8,41 +16,63 @@ pub enum Opcode {
     InverseQuery,
     /// 2: a server status request (STATUS)
     Status,
+    /// 4: a zone change notification (NOTIFY, RFC 1996)
+    Notify,
+    /// 5: a dynamic update (UPDATE, RFC 2136)
+    Update,
+    /// 6: DNS stateful operations (DSO, RFC 8490)
+    Dso,
+    /// An opcode unassigned in the registry.
+    Unknown(u8),
 }
 
-impl Into<u8> for Opcode {
-    fn into(self) -> u8 {
-        match self {
+impl From<Opcode> for u8 {
+    fn from(val: Opcode) -> Self {
+        match val {
             Opcode::Query => 0,
             Opcode::InverseQuery => 1,
             Opcode::Status => 2,
+            Opcode::Notify => 4,
+            Opcode::Update => 5,
+            Opcode::Dso => 6,
+            Opcode::Unknown(value) => value,
         }
     }
 }
 
-impl<'a> Opcode {
-    pub fn as_bitvec(self) -> BitVec<usize, Msb0> {
-        match self {
-            Opcode::Query => bitvec![usize, Msb0; 0, 0, 0, 0],
-            Opcode::InverseQuery => bitvec![usize, Msb0; 0, 0, 0, 1],
-            Opcode::Status => bitvec![usize, Msb0; 0, 0, 1, 0],
+impl Opcode {
+    /// Fail unless the opcode fits in 4 bits, and is not an unassigned
+    /// opcode standing for an assigned one, such as `Unknown(0)` for QUERY.
+    pub fn check(&self) -> Result<(), DnsError> {
+        let value = u8::from(*self);
+        if value > MAX_OPCODE || Self::from(value) != *self {
+            return Err(DnsError::BadOpcode(value));
         }
+        Ok(())
     }
-}
 
-impl TryFrom<u8> for Opcode {
-    type Error = anyhow::Error;
+    /// The 4 bits of the opcode that go in the header.
+    pub fn as_bitvec(&self) -> BitVec<usize, Msb0> {
+        let mut bv = BitVec::<usize, Msb0>::new();
+        bv.extend_from_bitslice(&u8::from(*self).view_bits::<Msb0>()[4..]);
+        bv
+    }
+}
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        let op = match value {
+impl From<u8> for Opcode {
+    fn from(value: u8) -> Self {
+        match value {
             0 => Self::Query,
             1 => Self::InverseQuery,
             2 => Self::Status,
-            other => anyhow::bail!("Unknown opcode {other}"),
-        };
-        Ok(op)
+            4 => Self::Notify,
+            5 => Self::Update,
+            6 => Self::Dso,
+            other => Self::Unknown(other),
+        }
     }
 }
+
 #[cfg(test)]
 mod tests_opcode {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
@@ -63,13 +93,30 @@ mod tests_opcode {
 
     #[test]
     fn test_all_convert() {
-        for i in 0..2 {
-            let opcode: Opcode = i.try_into().unwrap();
+        for i in 0..16 {
+            let opcode: Opcode = i.into();
             let n_opcode: u8 = opcode.into();
             assert_eq!(i, n_opcode);
 
             let bv: BitVec<usize, Msb0> = opcode.as_bitvec();
             assert_eq!(bv.len(), 4); // one octets
+            assert_eq!(bv.load_be::<u8>(), i);
+        }
+        assert_eq!(Opcode::from(4), Opcode::Notify);
+        assert_eq!(Opcode::from(5), Opcode::Update);
+        assert_eq!(Opcode::from(6), Opcode::Dso);
+        assert_eq!(Opcode::from(3), Opcode::Unknown(3));
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(Opcode::Dso.check().is_ok());
+        assert!(Opcode::Unknown(15).check().is_ok());
+        for value in [0, 4, 16, 255] {
+            assert_eq!(
+                Opcode::Unknown(value).check(),
+                Err(DnsError::BadOpcode(value))
+            );
         }
     }
 }
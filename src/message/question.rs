@@ -1,99 +1,116 @@
-use crate::rr::{record_class::Class, record_type::RecordType};
+use crate::error::DnsError;
+use crate::rr::{
+    record::{fqdn, labels_to_name, name_to_labels},
+    record_class::Class,
+    record_type::RecordType,
+};
 use bitvec::prelude::*;
 
 use log::debug;
-use nom::{combinator::map_res, number::complete::be_u16, IResult};
-
-use super::parser::{take_nibble, take_u16, BitInput};
-
-const MAX_LABEL_BYTES: usize = 64;
-
-#[derive(Debug, Clone)]
-pub struct Question<'a> {
-    labels: Vec<&'a str>,
-    record_type: RecordType,
-    record_qclass: Class,
+use nom::{
+    combinator::{map, map_res},
+    IResult,
+};
+
+use super::{
+    name::{parse_name, NameCompressor},
+    parser::{take_u16, BitInput},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Question {
+    /// The fully qualified domain name asked about (QNAME), e.g. `google.com.`.
+    pub name: String,
+    /// The type of the query (QTYPE).
+    pub record_type: RecordType,
+    /// The class of the query (QCLASS).
+    pub record_qclass: Class,
 }
-impl<'a> Question<'a> {
-    pub(crate) fn new(labels: Vec<&'a str>, record_type: RecordType, record_qclass: Class) -> Self {
+impl Question {
+    pub fn new(name: &str, record_type: RecordType, record_qclass: Class) -> Self {
         Self {
-            labels,
+            name: fqdn(name),
             record_type,
             record_qclass,
         }
     }
+
+    /// Whether `other` asks the same question, names being compared case-insensitively.
+    pub fn matches(&self, other: &Question) -> bool {
+        self.name.eq_ignore_ascii_case(&other.name)
+            && self.record_type == other.record_type
+            && self.record_qclass == other.record_qclass
+    }
 }
 
-impl<'a> Question<'a> {
-    pub fn as_bitvec(self) -> Result<BitVec<usize, Msb0>, std::io::Error> {
+impl Question {
+    pub fn as_bitvec(&self) -> Result<BitVec<usize, Msb0>, DnsError> {
         let mut bv: BitVec<usize, Msb0> = BitVec::<usize, Msb0>::new();
+        self.write(&mut bv, &mut NameCompressor::new(false))?;
+        Ok(bv)
+    }
 
-        for label in self.labels {
-            debug!("Serializing label {:?}", label);
-            // The mapping of domain names to labels is defined in RFC 1035:
-            // 2.3.1. Preferred name syntax
-            let len = label.len();
-            if len >= MAX_LABEL_BYTES {
-                let fmt = format!("Label {label} too long");
-                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, fmt));
-            }
-            let len = u8::try_from(len).unwrap();
-            bv.extend_from_bitslice(len.view_bits::<Msb0>());
-            label
-                .chars()
-                .map(|ch| ch.try_into().unwrap())
-                .for_each(|byte: u8| bv.extend_from_bitslice(byte.view_bits::<Msb0>()));
-        }
+    /// Append this question to `bv`, which holds the message written so far.
+    /// Fails for unknown types or classes standing for known ones, as the
+    /// response would not match the question.
+    pub fn write(
+        &self,
+        bv: &mut BitVec<usize, Msb0>,
+        names: &mut NameCompressor,
+    ) -> Result<(), DnsError> {
+        self.record_type.check()?;
+        self.record_qclass.check()?;
+        let labels: Vec<&str> = name_to_labels(&self.name).collect();
+        names.write_name(bv, &labels)?;
 
         debug!("Serializing record type {:?}", self.record_type);
-        bv.extend_from_bitslice(self.record_type.as_bitslice());
+        bv.extend_from_bitslice(self.record_type.as_bitvec().as_bitslice());
         debug!("Serializing record class {:?}", self.record_qclass);
-        bv.extend_from_bitslice(self.record_qclass.as_bitslice());
+        bv.extend_from_bitslice(self.record_qclass.as_bitvec().as_bitslice());
 
-        Ok(bv)
+        Ok(())
     }
 
-    pub fn deserialize(i: BitInput<'a>) -> IResult<(&'a [u8], usize), Self> {
-        let (i, labels) = Self::parse_labels_then_zero(i).unwrap();
+    /// Parse a question from `i`, where `msg` is the whole message it was taken from.
+    pub fn deserialize<'a>(
+        msg: &'a [u8],
+        i: BitInput<'a>,
+    ) -> IResult<(&'a [u8], usize), Self, DnsError> {
+        let (i, labels) = Self::parse_labels_then_zero(msg, i)?;
 
-        let (i, record_type) = map_res(take_nibble, RecordType::try_from)(i).unwrap();
-        let (i, record_qclass) = map_res(take_nibble, Class::try_from)(i).unwrap();
+        let (i, record_type) = map(take_u16, RecordType::from)(i)?;
+        let (i, record_qclass) = map(take_u16, Class::from)(i)?;
 
         Ok((
             i,
             Self {
-                labels,
+                name: labels_to_name(&labels),
                 record_type,
                 record_qclass,
             },
         ))
     }
-    pub fn parse_labels_then_zero(i: BitInput<'a>) -> IResult<(&'a [u8], usize), Vec<&'a str>> {
-        let mut labels = Vec::new();
-        let mut ix = i.0;
-        loop {
-            let (i, label) = Self::parse_label(ix).unwrap();
-            ix = i;
-            debug!("Found label {}", label);
-            let len = label.len();
-            labels.push(label);
-            if len == 0 {
-                return Ok(((i, i.len()), labels));
-            }
-        }
+    /// Parse the labels of a name, following compression pointers into `msg`.
+    pub fn parse_labels_then_zero<'a>(
+        msg: &'a [u8],
+        i: BitInput<'a>,
+    ) -> IResult<(&'a [u8], usize), Vec<&'a str>, DnsError> {
+        let (i, labels) = parse_name(msg, i.0)?;
+        debug!("Found labels {:?}", labels);
+        Ok(((i, 0), labels))
     }
-    pub fn parse_label(i: &'a [u8]) -> IResult<&'a [u8], &'a str> {
+    pub fn parse_label(i: &[u8]) -> IResult<&[u8], &str, DnsError> {
         let parse_len = map_res(nom::number::complete::be_u8, |num| {
             if num >= 64 {
-                Err(format!(
-                    "DNS name labels must be <=63 bytes but this one is {num}"
-                ))
+                Err(DnsError::BadLabelLength(num.into()))
             } else {
                 Ok(num)
             }
         });
         let parse_label = nom::multi::length_data(parse_len);
-        map_res(parse_label, |bytes: &[u8]| std::str::from_utf8(bytes))(i)
+        map_res(parse_label, |bytes: &[u8]| {
+            std::str::from_utf8(bytes).map_err(|_| DnsError::NonUtf8Label)
+        })(i)
     }
 }
 
@@ -106,30 +123,112 @@ mod tests_question {
         let record_type = RecordType::A;
         let record_class = Class::IN;
 
-        let question = Question::new(vec!["google", "com"], record_type, record_class);
+        let question = Question::new("google.com", record_type, record_class);
 
         let bitvec: BitVec<usize, Msb0> = question.as_bitvec().unwrap();
         let mut expected = bitvec![usize, Msb0;];
 
         //
         expected.extend_from_bitslice(("google".len() as u8).view_bits::<Msb0>());
-        expected.extend_from_bitslice(('g' as u8).view_bits::<Msb0>());
-        expected.extend_from_bitslice(('o' as u8).view_bits::<Msb0>());
-        expected.extend_from_bitslice(('o' as u8).view_bits::<Msb0>());
-        expected.extend_from_bitslice(('g' as u8).view_bits::<Msb0>());
-        expected.extend_from_bitslice(('l' as u8).view_bits::<Msb0>());
-        expected.extend_from_bitslice(('e' as u8).view_bits::<Msb0>());
+        expected.extend_from_bitslice(b'g'.view_bits::<Msb0>());
+        expected.extend_from_bitslice(b'o'.view_bits::<Msb0>());
+        expected.extend_from_bitslice(b'o'.view_bits::<Msb0>());
+        expected.extend_from_bitslice(b'g'.view_bits::<Msb0>());
+        expected.extend_from_bitslice(b'l'.view_bits::<Msb0>());
+        expected.extend_from_bitslice(b'e'.view_bits::<Msb0>());
 
         //
         expected.extend_from_bitslice(("com".len() as u8).view_bits::<Msb0>());
-        expected.extend_from_bitslice(('c' as u8).view_bits::<Msb0>());
-        expected.extend_from_bitslice(('o' as u8).view_bits::<Msb0>());
-        expected.extend_from_bitslice(('m' as u8).view_bits::<Msb0>());
+        expected.extend_from_bitslice(b'c'.view_bits::<Msb0>());
+        expected.extend_from_bitslice(b'o'.view_bits::<Msb0>());
+        expected.extend_from_bitslice(b'm'.view_bits::<Msb0>());
+        expected.extend_from_bitslice(0u8.view_bits::<Msb0>());
 
         //
-        expected.extend_from_bitslice((1 as u16).view_bits::<Msb0>());
-        expected.extend_from_bitslice((1 as u16).view_bits::<Msb0>());
+        expected.extend_from_bitslice(1u16.view_bits::<Msb0>());
+        expected.extend_from_bitslice(1u16.view_bits::<Msb0>());
 
         assert_eq!(bitvec, expected);
     }
+
+    fn parse(msg: &[u8]) -> Result<Question, DnsError> {
+        let (rest, question) =
+            nom::bits::bits::<_, _, DnsError, DnsError, _>(|i| Question::deserialize(msg, i))(msg)?;
+        assert!(rest.is_empty());
+        Ok(question)
+    }
+
+    fn to_bytes(question: &Question) -> Vec<u8> {
+        let bv = question.as_bitvec().unwrap();
+        bv.chunks(8).map(|byte| byte.load_be::<u8>()).collect()
+    }
+
+    /// `example.` followed by the given QTYPE and QCLASS.
+    fn wire(record_type: u16, class: u16) -> Vec<u8> {
+        let mut bytes = vec![7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0];
+        bytes.extend_from_slice(&record_type.to_be_bytes());
+        bytes.extend_from_slice(&class.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let question = Question::new("www.google.com.", RecordType::AAAA, Class::CH);
+        let bytes = to_bytes(&question);
+        assert_eq!(parse(&bytes).unwrap(), question);
+    }
+
+    #[test]
+    fn test_round_trip_every_record_type() {
+        for value in 0..=u16::MAX {
+            let bytes = wire(value, 1);
+            let question = parse(&bytes).unwrap();
+            assert_eq!(
+                question,
+                Question::new("example.", RecordType::from(value), Class::IN)
+            );
+            assert_eq!(to_bytes(&question), bytes);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_every_class() {
+        for value in 0..=u16::MAX {
+            let bytes = wire(1, value);
+            let question = parse(&bytes).unwrap();
+            assert_eq!(
+                question,
+                Question::new("example.", RecordType::A, Class::from(value))
+            );
+            assert_eq!(to_bytes(&question), bytes);
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let question = Question::new("Example.COM", RecordType::A, Class::IN);
+        assert!(question.matches(&Question::new("example.com.", RecordType::A, Class::IN)));
+        assert!(!question.matches(&Question::new("example.org.", RecordType::A, Class::IN)));
+        assert!(!question.matches(&Question::new("example.com.", RecordType::MX, Class::IN)));
+        assert!(!question.matches(&Question::new("example.com.", RecordType::A, Class::CH)));
+    }
+
+    #[test]
+    fn test_aliased_unknown() {
+        let question = Question::new("example.", RecordType::Unknown(1), Class::IN);
+        assert_eq!(
+            question.as_bitvec().unwrap_err(),
+            DnsError::BadRecordType(1)
+        );
+        let question = Question::new("example.", RecordType::A, Class::Unknown(1));
+        assert_eq!(question.as_bitvec().unwrap_err(), DnsError::BadClass(1));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let bytes = wire(1, 1);
+        for len in 0..bytes.len() {
+            assert_eq!(parse(&bytes[..len]).unwrap_err(), DnsError::Truncated);
+        }
+    }
 }
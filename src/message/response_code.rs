@@ -1,6 +1,18 @@
 use bitvec::prelude::*;
 
-#[derive(Debug, Clone, Copy)]
+use crate::error::DnsError;
+
+/// The largest response code, as they are 12 bits long.
+pub const MAX_RESPONSE_CODE: u16 = 0xfff;
+
+/// The largest response code fitting in the header.
+pub const MAX_HEADER_RESPONSE_CODE: u16 = 0xf;
+
+/// The outcome of a query, from the IANA DNS RCODE registry.
+///
+/// Codes up to 15 fit in the header, larger ones are 12 bits long, their
+/// upper 8 bits being in the OPT record of the message (RFC 6891 6.1.3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ResponseCode {
     NoError,
     /// The name server was unable to interpret the query
@@ -23,45 +35,130 @@ pub enum ResponseCode {
     /// or a name server may not wish to perform
     /// a particular operation (e.g., zone
     Refused,
+    /// YXDOMAIN: a name exists when it should not (RFC 2136).
+    NameExists,
+    /// YXRRSET: an RRset exists when it should not (RFC 2136).
+    RRSetExists,
+    /// NXRRSET: an RRset that should exist does not (RFC 2136).
+    RRSetMissing,
+    /// NOTAUTH: the server is not authoritative for the zone (RFC 2136), or
+    /// the request is not authorized (RFC 8945).
+    NotAuthoritative,
+    /// NOTZONE: a name is not within the zone (RFC 2136).
+    NotZone,
+    /// DSOTYPENI: the DSO type is not implemented (RFC 8490).
+    DsoTypeNotImplemented,
+    /// BADVERS: the EDNS version is not supported (RFC 6891). The same code
+    /// is BADSIG in a TSIG record, see [`ResponseCode::BAD_SIGNATURE`].
+    BadVersion,
+    /// BADKEY: the key is not recognized (RFC 8945).
+    BadKey,
+    /// BADTIME: the signature is out of its time window (RFC 8945).
+    BadTime,
+    /// BADMODE: the TKEY mode is not supported (RFC 2930).
+    BadMode,
+    /// BADNAME: the key name is a duplicate (RFC 2930).
+    BadName,
+    /// BADALG: the algorithm is not supported (RFC 2930).
+    BadAlgorithm,
+    /// BADTRUNC: the truncation is bad (RFC 8945).
+    BadTruncation,
+    /// BADCOOKIE: the server cookie is bad or missing (RFC 7873).
+    BadCookie,
+    /// A code unassigned or reserved in the registry.
+    Unknown(u16),
 }
-impl<'a> ResponseCode {
-    pub fn as_bitvec(self) -> BitVec<usize, Msb0> {
-        match self {
-            ResponseCode::NoError => bitvec![usize, Msb0; 0, 0, 0, 0],
-            ResponseCode::FormatError => bitvec![usize, Msb0;  0, 0, 0, 1],
-            ResponseCode::ServerFailure => bitvec![usize, Msb0; 0, 0, 1, 0],
-            ResponseCode::NameError => bitvec![usize, Msb0; 0, 0, 1, 1],
-            ResponseCode::NotImplemented => bitvec![usize, Msb0; 0, 1, 0, 0],
-            ResponseCode::Refused => bitvec![usize, Msb0; 0, 1, 0, 1],
+
+impl ResponseCode {
+    /// BADSIG: the TSIG signature failed to verify (RFC 8945), which shares
+    /// its code with BADVERS.
+    pub const BAD_SIGNATURE: ResponseCode = ResponseCode::BadVersion;
+
+    /// The 4 bits of the code that go in the header.
+    pub fn as_bitvec(&self) -> BitVec<usize, Msb0> {
+        let mut bv = BitVec::<usize, Msb0>::new();
+        bv.extend_from_bitslice(&self.header_bits().view_bits::<Msb0>()[4..]);
+        bv
+    }
+
+    /// The lower 4 bits of the code, which go in the header.
+    pub fn header_bits(&self) -> u8 {
+        (u16::from(*self) & 0xf) as u8
+    }
+
+    /// The upper 8 bits of the code, which go in the OPT record.
+    pub fn extended_bits(&self) -> u8 {
+        (u16::from(*self) >> 4) as u8
+    }
+
+    /// Fail unless the code is at most `max`, and is not an unassigned code
+    /// standing for an assigned one, such as `Unknown(3)` for NXDOMAIN.
+    pub fn check(&self, max: u16) -> Result<(), DnsError> {
+        let value = u16::from(*self);
+        if value > max || Self::from(value) != *self {
+            return Err(DnsError::BadResponseCode(value));
         }
+        Ok(())
     }
-}
 
-impl TryFrom<u8> for ResponseCode {
-    type Error = anyhow::Error;
+    /// The code made of the `header` bits and the `extended` ones of the OPT record.
+    pub fn from_parts(header: u8, extended: u8) -> Self {
+        Self::from(u16::from(extended) << 4 | u16::from(header & 0xf))
+    }
+}
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        let op: ResponseCode = match value {
+impl From<u16> for ResponseCode {
+    fn from(value: u16) -> Self {
+        match value {
             0 => Self::NoError,
             1 => Self::FormatError,
             2 => Self::ServerFailure,
             3 => Self::NameError,
             4 => Self::NotImplemented,
             5 => Self::Refused,
-            other => anyhow::bail!("Unknown response_code {other}"),
-        };
-        Ok(op)
+            6 => Self::NameExists,
+            7 => Self::RRSetExists,
+            8 => Self::RRSetMissing,
+            9 => Self::NotAuthoritative,
+            10 => Self::NotZone,
+            11 => Self::DsoTypeNotImplemented,
+            16 => Self::BadVersion,
+            17 => Self::BadKey,
+            18 => Self::BadTime,
+            19 => Self::BadMode,
+            20 => Self::BadName,
+            21 => Self::BadAlgorithm,
+            22 => Self::BadTruncation,
+            23 => Self::BadCookie,
+            other => Self::Unknown(other),
+        }
     }
 }
-impl Into<u8> for ResponseCode {
-    fn into(self) -> u8 {
-        match self {
-            Self::NoError => 0,
-            Self::FormatError => 1,
-            Self::ServerFailure => 2,
-            Self::NameError => 3,
-            Self::NotImplemented => 4,
-            Self::Refused => 5,
+
+impl From<ResponseCode> for u16 {
+    fn from(val: ResponseCode) -> Self {
+        match val {
+            ResponseCode::NoError => 0,
+            ResponseCode::FormatError => 1,
+            ResponseCode::ServerFailure => 2,
+            ResponseCode::NameError => 3,
+            ResponseCode::NotImplemented => 4,
+            ResponseCode::Refused => 5,
+            ResponseCode::NameExists => 6,
+            ResponseCode::RRSetExists => 7,
+            ResponseCode::RRSetMissing => 8,
+            ResponseCode::NotAuthoritative => 9,
+            ResponseCode::NotZone => 10,
+            ResponseCode::DsoTypeNotImplemented => 11,
+            ResponseCode::BadVersion => 16,
+            ResponseCode::BadKey => 17,
+            ResponseCode::BadTime => 18,
+            ResponseCode::BadMode => 19,
+            ResponseCode::BadName => 20,
+            ResponseCode::BadAlgorithm => 21,
+            ResponseCode::BadTruncation => 22,
+            ResponseCode::BadCookie => 23,
+            ResponseCode::Unknown(value) => value,
         }
     }
 }
@@ -79,13 +176,48 @@ mod tests_response_code {
     }
     #[test]
     fn test_all_convert() {
-        for i in 0..2 {
-            let response_code: ResponseCode = i.try_into().unwrap();
-            let n_response_code: u8 = response_code.into();
+        // RCODEs are 12 bits long
+        for i in 0..4096u16 {
+            let response_code: ResponseCode = i.into();
+            let n_response_code: u16 = response_code.into();
             assert_eq!(i, n_response_code);
 
             let bv: BitVec<usize, Msb0> = response_code.as_bitvec();
-            assert_eq!(bv.len(), 4); // one octets
+            assert_eq!(bv.len(), 4); // the header nibble
+            assert_eq!(bv.load_be::<u16>(), i & 0xf);
         }
+        assert_eq!(ResponseCode::from(5), ResponseCode::Refused);
+        assert_eq!(ResponseCode::from(12), ResponseCode::Unknown(12));
+        assert_eq!(ResponseCode::BAD_SIGNATURE, ResponseCode::from(16));
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(ResponseCode::BadCookie.check(MAX_RESPONSE_CODE).is_ok());
+        assert!(ResponseCode::Unknown(4095).check(MAX_RESPONSE_CODE).is_ok());
+        assert_eq!(
+            ResponseCode::BadCookie.check(MAX_HEADER_RESPONSE_CODE),
+            Err(DnsError::BadResponseCode(23))
+        );
+        assert_eq!(
+            ResponseCode::Unknown(3).check(MAX_RESPONSE_CODE),
+            Err(DnsError::BadResponseCode(3))
+        );
+        assert_eq!(
+            ResponseCode::Unknown(4096).check(MAX_RESPONSE_CODE),
+            Err(DnsError::BadResponseCode(4096))
+        );
+    }
+
+    #[test]
+    fn test_parts() {
+        let code = ResponseCode::BadCookie;
+        assert_eq!((code.header_bits(), code.extended_bits()), (7, 1));
+        assert_eq!(ResponseCode::from_parts(7, 1), code);
+        assert_eq!(ResponseCode::from_parts(7, 0), ResponseCode::RRSetExists);
+        assert_eq!(
+            ResponseCode::from_parts(0xf, 0xff),
+            ResponseCode::Unknown(4095)
+        );
     }
 }
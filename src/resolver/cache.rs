@@ -0,0 +1,573 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use super::chain::Chain;
+use crate::message::{message::Message, response_code::ResponseCode};
+use crate::rr::{rdata::RData, record::Record, record_class::Class, record_type::RecordType};
+
+/// A source of the current time, which tests can control.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// The monotonic clock of the operating system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// How big the cache can get, and how long records stay in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// How many questions are cached before the least recently used are evicted.
+    pub capacity: usize,
+    /// Records with a lower TTL are cached for this many seconds.
+    pub min_ttl: u32,
+    /// Records with a higher TTL are only cached for this many seconds.
+    pub max_ttl: u32,
+    /// Negative answers are only cached for this many seconds (RFC 2308 5).
+    pub max_negative_ttl: u32,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            min_ttl: 0,
+            max_ttl: 86_400,
+            max_negative_ttl: 10_800,
+        }
+    }
+}
+
+/// How often the cache had the answer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// What the cache knows of a question.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cached {
+    /// The records answering it.
+    Records(Vec<Record>),
+    /// The name does not exist (NXDOMAIN), as the SOA record of its zone tells.
+    NameError(Record),
+    /// The name has no records of this type (NODATA), as the SOA record of its zone tells.
+    NoData(Record),
+}
+
+impl Cached {
+    /// The same answer, with the TTL of its records set to `ttl`.
+    fn with_ttl(&self, ttl: u32) -> Self {
+        let with_ttl = |record: &Record| Record {
+            ttl,
+            ..record.clone()
+        };
+        match self {
+            Cached::Records(records) => Cached::Records(records.iter().map(with_ttl).collect()),
+            Cached::NameError(soa) => Cached::NameError(with_ttl(soa)),
+            Cached::NoData(soa) => Cached::NoData(with_ttl(soa)),
+        }
+    }
+}
+
+/// A question, with its name lowercased. A name that does not exist has no
+/// records of any type, so it is cached without record type.
+type Key = (String, Option<RecordType>, Class);
+
+#[derive(Debug)]
+struct Entry {
+    answer: Cached,
+    expires: Instant,
+    /// When the entry was last read or written, as a position in `Cache::recency`.
+    used: u64,
+}
+
+/// Keeps the answers to questions until their TTL runs out, including the
+/// negative ones (RFC 2308).
+#[derive(Debug)]
+pub struct Cache {
+    config: CacheConfig,
+    clock: Box<dyn Clock>,
+    entries: HashMap<Key, Entry>,
+    /// The keys of the entries, from the least to the most recently used.
+    recency: BTreeMap<u64, Key>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self::with_clock(config, SystemClock)
+    }
+
+    /// A cache telling the time with `clock` instead of [`SystemClock`].
+    pub fn with_clock(config: CacheConfig, clock: impl Clock + 'static) -> Self {
+        Self {
+            config,
+            clock: Box::new(clock),
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// What is known of a question, with the TTL of the records decremented
+    /// by the time they spent in the cache. A CNAME record of the name answers
+    /// for every type, as the alias to follow, but not the lack of one.
+    pub fn get(&mut self, name: &str, record_type: RecordType, class: Class) -> Option<Cached> {
+        let name = name.to_lowercase();
+        let answer = self
+            .get_key((name.clone(), Some(record_type), class))
+            .or_else(|| self.get_key((name.clone(), None, class)))
+            .or_else(|| match record_type {
+                RecordType::CNAME => None,
+                _ => match self.get_key((name, Some(RecordType::CNAME), class)) {
+                    Some(records @ Cached::Records(_)) => Some(records),
+                    _ => None,
+                },
+            });
+        match answer {
+            Some(_) => self.stats.hits += 1,
+            None => self.stats.misses += 1,
+        }
+        answer
+    }
+
+    /// Cache `records` as the answer to a question, for the lowest of their
+    /// TTLs clamped to the configured bounds. Nothing is cached without records.
+    pub fn insert(
+        &mut self,
+        name: &str,
+        record_type: RecordType,
+        class: Class,
+        records: Vec<Record>,
+    ) {
+        let Some(ttl) = records.iter().map(|record| record.ttl).min() else {
+            return;
+        };
+        let ttl = ttl.clamp(self.config.min_ttl, self.config.max_ttl);
+        let key = (name.to_lowercase(), Some(record_type), class);
+        self.store(key, Cached::Records(records), ttl);
+    }
+
+    /// Cache `response` as the answer to a question: its records when it has
+    /// some, or the fact that the name or records do not exist.
+    ///
+    /// A negative answer is cached for the lowest of the TTL and the minimum
+    /// field of the SOA record in the authority section (RFC 2308 5), and not
+    /// at all without one. It is about the name the aliases of the answer
+    /// lead to, which is where it is cached, the aliases being cached under
+    /// their owners (RFC 2308 2.1).
+    pub fn insert_response(
+        &mut self,
+        name: &str,
+        record_type: RecordType,
+        class: Class,
+        response: &Message,
+    ) {
+        let resp_code = response.response_code();
+        if resp_code == ResponseCode::NoError && !response.answer.is_empty() {
+            self.insert(name, record_type, class, response.answer.clone());
+            return;
+        }
+        let soa = response
+            .authority
+            .iter()
+            .find_map(|record| match record.rdata {
+                RData::SOA { minimum, .. } => Some((record, minimum)),
+                _ => None,
+            });
+        let Some((soa, minimum)) = soa else {
+            debug!("Not caching {resp_code:?} for {name} without SOA record");
+            return;
+        };
+        let mut chain = Chain::new(name);
+        if let Err(e) = chain.follow(record_type, &response.answer) {
+            debug!("Not caching {resp_code:?} for {name}: {e}");
+            return;
+        }
+        for alias in &chain.aliases {
+            self.insert(&alias.name, alias.record_type, class, vec![alias.clone()]);
+        }
+        let ttl = soa.ttl.min(minimum).min(self.config.max_negative_ttl);
+        let name = chain.name.to_lowercase();
+        match resp_code {
+            ResponseCode::NoError => {
+                let key = (name, Some(record_type), class);
+                self.store(key, Cached::NoData(soa.clone()), ttl);
+            }
+            ResponseCode::NameError => {
+                let key = (name, None, class);
+                self.store(key, Cached::NameError(soa.clone()), ttl);
+            }
+            _ => (),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// How many questions have a cached answer, including expired ones not yet evicted.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Mark the entry for `key` as the most recently used.
+    fn touch(&mut self, key: &Key) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used);
+            self.tick += 1;
+            entry.used = self.tick;
+            self.recency.insert(self.tick, key.clone());
+        }
+    }
+
+    /// The unexpired answer cached under `key`, without counting a hit or miss.
+    fn get_key(&mut self, key: Key) -> Option<Cached> {
+        let now = self.clock.now();
+        let entry = self.entries.get(&key)?;
+        if entry.expires <= now {
+            debug!("Cached answer for {} expired", key.0);
+            self.remove(&key);
+            return None;
+        }
+        let answer = entry
+            .answer
+            .with_ttl((entry.expires - now).as_secs() as u32);
+        self.touch(&key);
+        Some(answer)
+    }
+
+    /// Cache `answer` under `key` for `ttl` seconds, evicting the least
+    /// recently used entries when full.
+    fn store(&mut self, key: Key, answer: Cached, ttl: u32) {
+        if ttl == 0 || self.config.capacity == 0 {
+            return;
+        }
+        self.remove(&key);
+        while self.entries.len() >= self.config.capacity {
+            let Some((_, lru)) = self.recency.pop_first() else {
+                break;
+            };
+            debug!("Evicting cached answer for {}", lru.0);
+            self.entries.remove(&lru);
+        }
+        let expires = self.clock.now() + Duration::from_secs(ttl.into());
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                answer,
+                expires,
+                used: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+        }
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(CacheConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests_cache {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::sync::{Arc, Mutex};
+
+    /// A clock which only moves when told to.
+    #[derive(Debug, Clone)]
+    struct MockClock(Arc<Mutex<Instant>>);
+
+    impl MockClock {
+        fn new() -> Self {
+            Self(Arc::new(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, secs: u64) {
+            *self.0.lock().unwrap() += Duration::from_secs(secs);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn a(name: &str, ttl: u32, last_octet: u8) -> Record {
+        Record {
+            name: name.to_string(),
+            record_type: RecordType::A,
+            class: Class::IN,
+            ttl,
+            rdata: RData::A(Ipv4Addr::new(192, 0, 2, last_octet)),
+        }
+    }
+
+    fn cache(config: CacheConfig) -> (Cache, MockClock) {
+        let clock = MockClock::new();
+        (Cache::with_clock(config, clock.clone()), clock)
+    }
+
+    fn ttls(cached: Option<Cached>) -> Vec<u32> {
+        let Some(Cached::Records(records)) = cached else {
+            panic!("{cached:?} is not records");
+        };
+        records.iter().map(|record| record.ttl).collect()
+    }
+
+    #[test]
+    fn test_decrement_ttl() {
+        let (mut cache, clock) = cache(CacheConfig::default());
+        let records = vec![a("example.com.", 300, 1), a("example.com.", 200, 2)];
+        cache.insert("example.com.", RecordType::A, Class::IN, records);
+
+        assert_eq!(
+            ttls(cache.get("example.com.", RecordType::A, Class::IN)),
+            vec![200, 200]
+        );
+        clock.advance(150);
+        assert_eq!(
+            ttls(cache.get("Example.COM.", RecordType::A, Class::IN)),
+            vec![50, 50]
+        );
+        clock.advance(50);
+        assert!(cache
+            .get("example.com.", RecordType::A, Class::IN)
+            .is_none());
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 1 });
+    }
+
+    #[test]
+    fn test_key() {
+        let (mut cache, _) = cache(CacheConfig::default());
+        cache.insert(
+            "example.com.",
+            RecordType::A,
+            Class::IN,
+            vec![a("example.com.", 60, 1)],
+        );
+        assert!(cache
+            .get("example.com.", RecordType::AAAA, Class::IN)
+            .is_none());
+        assert!(cache
+            .get("example.com.", RecordType::A, Class::CH)
+            .is_none());
+        assert!(cache
+            .get("www.example.com.", RecordType::A, Class::IN)
+            .is_none());
+        assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 3 });
+    }
+
+    #[test]
+    fn test_ttl_clamps() {
+        let config = CacheConfig {
+            min_ttl: 30,
+            max_ttl: 3600,
+            ..CacheConfig::default()
+        };
+        let (mut cache, clock) = cache(config);
+        cache.insert("low.", RecordType::A, Class::IN, vec![a("low.", 0, 1)]);
+        cache.insert(
+            "high.",
+            RecordType::A,
+            Class::IN,
+            vec![a("high.", 604_800, 1)],
+        );
+        assert_eq!(ttls(cache.get("low.", RecordType::A, Class::IN)), vec![30]);
+        assert_eq!(
+            ttls(cache.get("high.", RecordType::A, Class::IN)),
+            vec![3600]
+        );
+        clock.advance(3600);
+        assert!(cache.get("high.", RecordType::A, Class::IN).is_none());
+    }
+
+    #[test]
+    fn test_nothing_to_cache() {
+        let (mut cache, _) = cache(CacheConfig::default());
+        cache.insert("empty.", RecordType::A, Class::IN, Vec::new());
+        cache.insert("zero.", RecordType::A, Class::IN, vec![a("zero.", 0, 1)]);
+        assert!(cache.is_empty());
+    }
+
+    /// A response to `name` with `resp_code`, and an SOA record with `ttl`
+    /// and `minimum` in its authority section.
+    fn negative(name: &str, resp_code: ResponseCode, ttl: u32, minimum: u32) -> Message {
+        let mut response = Message::new(1, name, RecordType::A, Class::IN).unwrap();
+        response.header.is_response = true;
+        response.header.resp_code = resp_code;
+        response.authority.push(Record {
+            name: "example.".to_string(),
+            record_type: RecordType::SOA,
+            class: Class::IN,
+            ttl,
+            rdata: RData::SOA {
+                mname: "ns.example.".to_string(),
+                rname: "hostmaster.example.".to_string(),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum,
+            },
+        });
+        response
+    }
+
+    #[test]
+    fn test_name_error() {
+        let (mut cache, clock) = cache(CacheConfig::default());
+        let response = negative("nx.example.", ResponseCode::NameError, 300, 60);
+        cache.insert_response("nx.example.", RecordType::A, Class::IN, &response);
+
+        // the lowest of the SOA TTL and minimum, for every type
+        for record_type in [RecordType::A, RecordType::MX] {
+            let Some(Cached::NameError(soa)) = cache.get("NX.example.", record_type, Class::IN)
+            else {
+                panic!("no name error cached");
+            };
+            assert_eq!(soa.name, "example.");
+            assert_eq!(soa.ttl, 60);
+        }
+        assert!(cache.get("nx.example.", RecordType::A, Class::CH).is_none());
+        clock.advance(60);
+        assert!(cache.get("nx.example.", RecordType::A, Class::IN).is_none());
+    }
+
+    #[test]
+    fn test_no_data() {
+        let (mut cache, clock) = cache(CacheConfig::default());
+        let response = negative("www.example.", ResponseCode::NoError, 30, 600);
+        cache.insert_response("www.example.", RecordType::A, Class::IN, &response);
+
+        let cached = cache.get("www.example.", RecordType::A, Class::IN);
+        assert!(matches!(cached, Some(Cached::NoData(soa)) if soa.ttl == 30));
+        assert!(cache
+            .get("www.example.", RecordType::AAAA, Class::IN)
+            .is_none());
+        clock.advance(30);
+        assert!(cache
+            .get("www.example.", RecordType::A, Class::IN)
+            .is_none());
+    }
+
+    #[test]
+    fn test_no_data_cname() {
+        let (mut cache, _) = cache(CacheConfig::default());
+        let response = negative("www.example.", ResponseCode::NoError, 300, 60);
+        cache.insert_response("www.example.", RecordType::CNAME, Class::IN, &response);
+
+        assert!(matches!(
+            cache.get("www.example.", RecordType::CNAME, Class::IN),
+            Some(Cached::NoData(_))
+        ));
+        // the lack of an alias says nothing of the other types
+        assert!(cache
+            .get("www.example.", RecordType::A, Class::IN)
+            .is_none());
+    }
+
+    #[test]
+    fn test_name_error_after_cname() {
+        let (mut cache, _) = cache(CacheConfig::default());
+        let cname = Record {
+            name: "www.example.".to_string(),
+            record_type: RecordType::CNAME,
+            class: Class::IN,
+            ttl: 600,
+            rdata: RData::CNAME("nx.example.".to_string()),
+        };
+        let mut response = negative("www.example.", ResponseCode::NameError, 300, 60);
+        response.answer.push(cname.clone());
+        cache.insert_response("www.example.", RecordType::A, Class::IN, &response);
+
+        // the alias exists, for every type
+        for record_type in [RecordType::A, RecordType::MX, RecordType::CNAME] {
+            assert_eq!(
+                cache.get("www.example.", record_type, Class::IN),
+                Some(Cached::Records(vec![cname.clone()]))
+            );
+        }
+        // its target does not
+        assert!(matches!(
+            cache.get("nx.example.", RecordType::A, Class::IN),
+            Some(Cached::NameError(soa)) if soa.ttl == 60
+        ));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_negative_not_cached() {
+        let config = CacheConfig {
+            max_negative_ttl: 10,
+            ..CacheConfig::default()
+        };
+        let (mut cache, clock) = cache(config);
+
+        // without SOA record
+        let mut response = negative("a.example.", ResponseCode::NameError, 300, 300);
+        response.authority.clear();
+        cache.insert_response("a.example.", RecordType::A, Class::IN, &response);
+        let response = negative("b.example.", ResponseCode::ServerFailure, 300, 300);
+        cache.insert_response("b.example.", RecordType::A, Class::IN, &response);
+        let response = negative("c.example.", ResponseCode::NameError, 300, 0);
+        cache.insert_response("c.example.", RecordType::A, Class::IN, &response);
+        assert!(cache.is_empty());
+
+        let response = negative("d.example.", ResponseCode::NameError, 300, 300);
+        cache.insert_response("d.example.", RecordType::A, Class::IN, &response);
+        clock.advance(10);
+        assert!(cache.get("d.example.", RecordType::A, Class::IN).is_none());
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let config = CacheConfig {
+            capacity: 2,
+            ..CacheConfig::default()
+        };
+        let (mut cache, _) = cache(config);
+        cache.insert("a.", RecordType::A, Class::IN, vec![a("a.", 60, 1)]);
+        cache.insert("b.", RecordType::A, Class::IN, vec![a("b.", 60, 2)]);
+        // `a.` is now more recently used than `b.`
+        assert!(cache.get("a.", RecordType::A, Class::IN).is_some());
+        cache.insert("c.", RecordType::A, Class::IN, vec![a("c.", 60, 3)]);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b.", RecordType::A, Class::IN).is_none());
+        assert!(cache.get("a.", RecordType::A, Class::IN).is_some());
+        assert!(cache.get("c.", RecordType::A, Class::IN).is_some());
+
+        // replacing an entry does not evict another one
+        cache.insert("c.", RecordType::A, Class::IN, vec![a("c.", 120, 4)]);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a.", RecordType::A, Class::IN).is_some());
+    }
+}
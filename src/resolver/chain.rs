@@ -0,0 +1,302 @@
+use log::debug;
+
+use crate::error::DnsError;
+use crate::message::message::MAX_NAME_BYTES;
+use crate::rr::record::{fqdn, name_to_labels};
+use crate::rr::{rdata::RData, record::Record, record_type::RecordType};
+
+/// The most CNAME and DNAME records followed for a single lookup.
+pub const MAX_ALIASES: usize = 16;
+
+/// How a name was resolved through CNAME and DNAME records, possibly over
+/// several responses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chain {
+    /// The CNAME and DNAME records followed, in order.
+    pub aliases: Vec<Record>,
+    /// The name the aliases lead to, or the name looked up when there are none.
+    pub name: String,
+    /// The records of the requested type at `name`, empty until a response has them.
+    pub records: Vec<Record>,
+    /// Every name gone through, lowercased.
+    visited: Vec<String>,
+}
+
+impl Chain {
+    /// A chain starting at `name`, without any alias yet.
+    pub fn new(name: &str) -> Self {
+        let name = fqdn(name);
+        Self {
+            aliases: Vec::new(),
+            visited: vec![name.to_lowercase()],
+            name,
+            records: Vec::new(),
+        }
+    }
+
+    /// Follow the CNAME and DNAME records of `answer` from the end of the
+    /// chain, until the records of `record_type` or the end of the aliases
+    /// in `answer`. A DNAME is followed before a CNAME, which would only be
+    /// the one synthesized from it (RFC 6672 3.1).
+    ///
+    /// Looking up a CNAME or a DNAME does not follow records of that type,
+    /// and looking up ANY takes the records of every type, aliases included.
+    pub fn follow(&mut self, record_type: RecordType, answer: &[Record]) -> Result<(), DnsError> {
+        loop {
+            let records: Vec<Record> = answer
+                .iter()
+                .filter(|record| {
+                    (record_type == RecordType::ANY || record.record_type == record_type)
+                        && record.name.eq_ignore_ascii_case(&self.name)
+                })
+                .cloned()
+                .collect();
+            if !records.is_empty() {
+                self.records = records;
+                return Ok(());
+            }
+            let Some((alias, name)) = self.next_alias(record_type, answer) else {
+                return Ok(());
+            };
+            self.push(alias.clone(), name)?;
+        }
+    }
+
+    /// The record aliasing the end of the chain in `answer`, and the name it leads to.
+    fn next_alias<'a>(
+        &self,
+        record_type: RecordType,
+        answer: &'a [Record],
+    ) -> Option<(&'a Record, String)> {
+        let dname = answer.iter().find_map(|record| match &record.rdata {
+            RData::DNAME(target) if record_type != RecordType::DNAME => {
+                substitute(&self.name, &record.name, target).map(|name| (record, name))
+            }
+            _ => None,
+        });
+        dname.or_else(|| {
+            answer.iter().find_map(|record| match &record.rdata {
+                RData::CNAME(target)
+                    if record_type != RecordType::CNAME
+                        && record.name.eq_ignore_ascii_case(&self.name) =>
+                {
+                    Some((record, fqdn(target)))
+                }
+                _ => None,
+            })
+        })
+    }
+
+    fn push(&mut self, alias: Record, name: String) -> Result<(), DnsError> {
+        if self.aliases.len() >= MAX_ALIASES {
+            return Err(DnsError::TooManyAliases {
+                len: self.aliases.len() + 1,
+                max: MAX_ALIASES,
+            });
+        }
+        // A fully qualified name takes one more byte on the wire than in text.
+        if name.len() + 1 > MAX_NAME_BYTES {
+            return Err(DnsError::NameTooLong(name.len() + 1));
+        }
+        let lowercase = name.to_lowercase();
+        if self.visited.contains(&lowercase) {
+            return Err(DnsError::AliasLoop(name));
+        }
+        debug!("Following {} to {name}", alias.name);
+        self.visited.push(lowercase);
+        self.aliases.push(alias);
+        self.name = name;
+        Ok(())
+    }
+}
+
+/// `name` with its `owner` suffix replaced by `target`, when `name` is below
+/// `owner`, e.g. `www.example.com.` with `example.com.` and `example.net.`
+/// gives `www.example.net.`.
+fn substitute(name: &str, owner: &str, target: &str) -> Option<String> {
+    let labels: Vec<&str> = name_to_labels(name).collect();
+    let owner: Vec<&str> = name_to_labels(owner).collect();
+    if labels.len() <= owner.len() {
+        return None;
+    }
+    let (prefix, suffix) = labels.split_at(labels.len() - owner.len());
+    if !suffix
+        .iter()
+        .zip(&owner)
+        .all(|(label, owner)| label.eq_ignore_ascii_case(owner))
+    {
+        return None;
+    }
+    let labels: Vec<&str> = prefix
+        .iter()
+        .copied()
+        .chain(name_to_labels(target))
+        .collect();
+    Some(fqdn(&labels.join(".")))
+}
+
+#[cfg(test)]
+mod tests_chain {
+    use super::*;
+    use crate::rr::record_class::Class;
+    use std::net::Ipv4Addr;
+
+    fn record(name: &str, rdata: RData) -> Record {
+        Record {
+            name: name.to_string(),
+            record_type: rdata.record_type(),
+            class: Class::IN,
+            ttl: 60,
+            rdata,
+        }
+    }
+
+    fn cname(name: &str, target: &str) -> Record {
+        record(name, RData::CNAME(target.to_string()))
+    }
+
+    fn a(name: &str) -> Record {
+        record(name, RData::A(Ipv4Addr::new(192, 0, 2, 1)))
+    }
+
+    #[test]
+    fn test_no_alias() {
+        let mut chain = Chain::new("www.example.com");
+        chain
+            .follow(RecordType::A, &[a("WWW.example.com.")])
+            .unwrap();
+        assert!(chain.aliases.is_empty());
+        assert_eq!(chain.name, "www.example.com.");
+        assert_eq!(chain.records, vec![a("WWW.example.com.")]);
+    }
+
+    #[test]
+    fn test_cname_chain() {
+        let answer = [
+            a("c.example.net."),
+            cname("www.example.com.", "b.example.com."),
+            cname("b.example.com.", "c.example.net."),
+        ];
+        let mut chain = Chain::new("www.example.com.");
+        chain.follow(RecordType::A, &answer).unwrap();
+        assert_eq!(chain.aliases, answer[1..]);
+        assert_eq!(chain.name, "c.example.net.");
+        assert_eq!(chain.records, answer[..1]);
+
+        // the records of the CNAME type are the answer
+        let mut chain = Chain::new("www.example.com.");
+        chain.follow(RecordType::CNAME, &answer).unwrap();
+        assert!(chain.aliases.is_empty());
+        assert_eq!(chain.records, answer[1..2]);
+    }
+
+    #[test]
+    fn test_any() {
+        let answer = [
+            cname("www.example.com.", "b.example.com."),
+            a("b.example.com."),
+        ];
+        let mut chain = Chain::new("www.example.com.");
+        chain.follow(RecordType::ANY, &answer).unwrap();
+        assert!(chain.aliases.is_empty());
+        assert_eq!(chain.records, answer[..1]);
+    }
+
+    #[test]
+    fn test_across_responses() {
+        let mut chain = Chain::new("www.example.com.");
+        chain
+            .follow(
+                RecordType::A,
+                &[cname("www.example.com.", "www.example.net.")],
+            )
+            .unwrap();
+        assert_eq!(chain.name, "www.example.net.");
+        assert!(chain.records.is_empty());
+
+        chain
+            .follow(RecordType::A, &[a("www.example.net.")])
+            .unwrap();
+        assert_eq!(chain.aliases.len(), 1);
+        assert_eq!(chain.records, vec![a("www.example.net.")]);
+    }
+
+    #[test]
+    fn test_dname() {
+        let answer = [
+            record("example.com.", RData::DNAME("example.net.".to_string())),
+            cname("www.example.com.", "www.example.net."),
+            a("www.example.net."),
+        ];
+        let mut chain = Chain::new("www.example.com.");
+        chain.follow(RecordType::A, &answer).unwrap();
+        assert_eq!(chain.aliases, answer[..1]);
+        assert_eq!(chain.records, answer[2..]);
+
+        // the owner of the DNAME itself is not substituted
+        let mut chain = Chain::new("example.com.");
+        chain.follow(RecordType::A, &answer).unwrap();
+        assert!(chain.aliases.is_empty());
+    }
+
+    #[test]
+    fn test_substitute() {
+        assert_eq!(
+            substitute("a.b.Example.com.", "example.COM.", "example.net."),
+            Some("a.b.example.net.".to_string())
+        );
+        assert_eq!(
+            substitute("a.", ".", "example."),
+            Some("a.example.".to_string())
+        );
+        assert_eq!(
+            substitute("a.example.com.", "example.com.", "."),
+            Some("a.".to_string())
+        );
+        assert_eq!(
+            substitute("example.com.", "example.com.", "example.net."),
+            None
+        );
+        assert_eq!(
+            substitute("a.example.org.", "example.com.", "example.net."),
+            None
+        );
+    }
+
+    #[test]
+    fn test_loop() {
+        let answer = [
+            cname("a.example.", "b.example."),
+            cname("b.example.", "A.example."),
+        ];
+        let mut chain = Chain::new("a.example.");
+        assert_eq!(
+            chain.follow(RecordType::A, &answer),
+            Err(DnsError::AliasLoop("A.example.".to_string()))
+        );
+
+        // a DNAME below its own target substitutes forever
+        let answer = [record("example.", RData::DNAME("a.example.".to_string()))];
+        let mut chain = Chain::new("www.example.");
+        assert!(chain.follow(RecordType::A, &answer).is_err());
+    }
+
+    #[test]
+    fn test_too_many_aliases() {
+        let answer: Vec<Record> = (0..=MAX_ALIASES)
+            .map(|ix| cname(&format!("{ix}.example."), &format!("{}.example.", ix + 1)))
+            .collect();
+        let mut chain = Chain::new("0.example.");
+        assert_eq!(
+            chain.follow(RecordType::A, &answer),
+            Err(DnsError::TooManyAliases {
+                len: MAX_ALIASES + 1,
+                max: MAX_ALIASES
+            })
+        );
+
+        let mut chain = Chain::new("1.example.");
+        chain.follow(RecordType::A, &answer).unwrap();
+        assert_eq!(chain.aliases.len(), MAX_ALIASES);
+    }
+}
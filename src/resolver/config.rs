@@ -0,0 +1,138 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use crate::client::{Transport, DEFAULT_ATTEMPTS, DEFAULT_TIMEOUT, DNS_PORT};
+use crate::error::DnsError;
+
+/// How long a name server answering SERVFAIL or REFUSED is skipped for.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Names with at least this many dots are tried as is first, as in resolv.conf(5).
+pub const DEFAULT_NDOTS: u8 = 1;
+
+/// The order name servers are tried in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Always start with the first name server, and fail over to the next ones.
+    #[default]
+    Sequential,
+    /// Start with the next name server for every query, as `options rotate` does.
+    RoundRobin,
+    /// Start with the name server with the lowest smoothed round trip time.
+    Fastest,
+}
+
+/// What a [`Resolver`](super::Resolver) queries, and how.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolverConfig {
+    /// The name servers, in order of preference.
+    pub nameservers: Vec<SocketAddr>,
+    pub strategy: Strategy,
+    /// How long a name server answering SERVFAIL or REFUSED is skipped for.
+    pub cooldown: Duration,
+    /// How long to wait for each response.
+    pub timeout: Duration,
+    /// How many times a query is sent to a name server before failing over to the next one.
+    pub attempts: usize,
+    pub transport: Transport,
+    /// Whether a truncated UDP response is retried over TCP.
+    pub retry_truncated: bool,
+    /// The domains appended to names that are not fully qualified, in order.
+    pub search: Vec<String>,
+    /// How many dots a name needs to be tried as is before the search domains.
+    pub ndots: u8,
+    /// Whether queries advertise EDNS(0) support (RFC 6891).
+    pub edns0: bool,
+    /// Whether queries ask the name servers to resolve names recursively.
+    pub recursion_desired: bool,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            nameservers: Vec::new(),
+            strategy: Strategy::default(),
+            cooldown: DEFAULT_COOLDOWN,
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+            transport: Transport::Udp,
+            retry_truncated: true,
+            search: Vec::new(),
+            ndots: DEFAULT_NDOTS,
+            edns0: false,
+            recursion_desired: true,
+        }
+    }
+}
+
+/// Parse a name server address, with an optional port which defaults to `default_port`.
+///
+/// Accepts `192.0.2.1`, `192.0.2.1:5353`, `2001:db8::1`, `[2001:db8::1]` and `[2001:db8::1]:5353`.
+pub fn parse_nameserver(s: &str, default_port: u16) -> Result<SocketAddr, DnsError> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let ip = s
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(s);
+    ip.parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, default_port))
+        .map_err(|_| DnsError::InvalidConfig(format!("{s} is not a valid name server address")))
+}
+
+impl ResolverConfig {
+    /// A config querying `nameservers`, which are parsed with [`parse_nameserver`]
+    /// and default to the DNS port.
+    pub fn with_nameservers<'a>(
+        nameservers: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, DnsError> {
+        let nameservers = nameservers
+            .into_iter()
+            .map(|s| parse_nameserver(s, DNS_PORT))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            nameservers,
+            ..Self::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests_config {
+    use super::*;
+
+    #[test]
+    fn test_parse_nameserver() {
+        let cases = [
+            ("192.0.2.1", "192.0.2.1:53"),
+            ("192.0.2.1:5353", "192.0.2.1:5353"),
+            ("2001:db8::1", "[2001:db8::1]:53"),
+            ("[2001:db8::1]", "[2001:db8::1]:53"),
+            ("[2001:db8::1]:5353", "[2001:db8::1]:5353"),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(
+                parse_nameserver(s, 53).unwrap(),
+                expected.parse::<SocketAddr>().unwrap()
+            );
+        }
+        assert!(parse_nameserver("ns1.example.", 53).is_err());
+        assert!(parse_nameserver("192.0.2.1:http", 53).is_err());
+        assert!(parse_nameserver("[192.0.2.1", 53).is_err());
+    }
+
+    #[test]
+    fn test_with_nameservers() {
+        let config = ResolverConfig::with_nameservers(["192.0.2.1", "[::1]:1053"]).unwrap();
+        assert_eq!(
+            config.nameservers,
+            vec![
+                "192.0.2.1:53".parse::<SocketAddr>().unwrap(),
+                "[::1]:1053".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.strategy, Strategy::Sequential);
+        assert!(ResolverConfig::with_nameservers(["nope"]).is_err());
+    }
+}
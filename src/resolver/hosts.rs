@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+use log::{debug, warn};
+
+use crate::error::DnsError;
+use crate::rr::record::{fqdn, name_to_labels};
+use crate::rr::{rdata::RData, record::Record, record_class::Class, record_type::RecordType};
+
+/// Where the static table of host names lives.
+pub const HOSTS_PATH: &str = "/etc/hosts";
+
+/// The TTL of records answered from the hosts file, which are never cached.
+const HOSTS_TTL: u32 = 0;
+
+/// The static table of host names of a hosts file, see hosts(5).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hosts {
+    /// The addresses of every canonical name and alias, keyed by lowercase fully qualified name.
+    addresses: HashMap<String, Vec<IpAddr>>,
+    /// The canonical names of every address.
+    names: HashMap<IpAddr, Vec<String>>,
+}
+
+impl Hosts {
+    /// Read the hosts file at `path`, usually [`HOSTS_PATH`].
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, DnsError> {
+        let path = path.as_ref();
+        debug!("Reading {}", path.display());
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parse the contents of a hosts file: an address, a canonical name and
+    /// its aliases per line, `#` starting a comment. Invalid lines are ignored.
+    pub fn parse(contents: &str) -> Self {
+        let mut hosts = Self::default();
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(address) = fields.next() else {
+                continue;
+            };
+            // The scope of link local IPv6 addresses does not matter to DNS.
+            let address = address.split('%').next().unwrap_or_default();
+            let Ok(address) = address.parse::<IpAddr>() else {
+                warn!("Ignoring hosts entry with invalid address {address}");
+                continue;
+            };
+            let names: Vec<String> = fields.map(|name| fqdn(&name.to_lowercase())).collect();
+            let Some(canonical) = names.first() else {
+                continue;
+            };
+            hosts
+                .names
+                .entry(address)
+                .or_default()
+                .push(canonical.clone());
+            for name in names {
+                let addresses = hosts.addresses.entry(name).or_default();
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+        }
+        hosts
+    }
+
+    /// The records answering `name`, when it is in the table: A and AAAA
+    /// records for a host name, or PTR records for a reverse name of an address.
+    pub fn lookup(&self, name: &str, record_type: RecordType, class: Class) -> Option<Vec<Record>> {
+        if class != Class::IN {
+            return None;
+        }
+        let name = fqdn(&name.to_lowercase());
+        let record = |rdata: RData| Record {
+            name: name.clone(),
+            record_type: rdata.record_type(),
+            class: Class::IN,
+            ttl: HOSTS_TTL,
+            rdata,
+        };
+        let records: Vec<Record> = match record_type {
+            RecordType::A | RecordType::AAAA => self
+                .addresses
+                .get(&name)?
+                .iter()
+                .filter_map(|address| match (address, record_type) {
+                    (IpAddr::V4(v4), RecordType::A) => Some(record(RData::A(*v4))),
+                    (IpAddr::V6(v6), RecordType::AAAA) => Some(record(RData::AAAA(*v6))),
+                    _ => None,
+                })
+                .collect(),
+            RecordType::PTR => self
+                .names
+                .get(&reverse_name_to_ip(&name)?)?
+                .iter()
+                .map(|host| record(RData::PTR(host.clone())))
+                .collect(),
+            _ => return None,
+        };
+        (!records.is_empty()).then_some(records)
+    }
+}
+
+/// The address a reverse name such as `1.0.0.127.in-addr.arpa.` or
+/// `...ip6.arpa.` stands for (RFC 1035 3.5, RFC 3596 2.5).
+pub fn reverse_name_to_ip(name: &str) -> Option<IpAddr> {
+    let labels: Vec<&str> = name_to_labels(name).collect();
+    match labels.as_slice() {
+        [d, c, b, a, in_addr, arpa]
+            if in_addr.eq_ignore_ascii_case("in-addr") && arpa.eq_ignore_ascii_case("arpa") =>
+        {
+            let octets = [a, b, c, d].map(|octet| octet.parse::<u8>().ok());
+            let [Some(a), Some(b), Some(c), Some(d)] = octets else {
+                return None;
+            };
+            Some(IpAddr::V4(Ipv4Addr::new(a, b, c, d)))
+        }
+        [nibbles @ .., ip6, arpa]
+            if nibbles.len() == 32
+                && ip6.eq_ignore_ascii_case("ip6")
+                && arpa.eq_ignore_ascii_case("arpa") =>
+        {
+            let mut address = 0u128;
+            for nibble in nibbles.iter().rev() {
+                if nibble.len() != 1 {
+                    return None;
+                }
+                address = address << 4 | u128::from_str_radix(nibble, 16).ok()?;
+            }
+            Some(IpAddr::V6(Ipv6Addr::from(address)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests_hosts {
+    use super::*;
+
+    const HOSTS: &str = "# The following lines are desirable for IPv4 capable hosts
+127.0.0.1       localhost
+127.0.1.1       Thishost.Example.org   thishost # the machine itself
+192.168.1.10    foo.example.org foo
+192.168.1.13    bar.example.org bar
+::1             localhost ip6-localhost ip6-loopback
+fe80::1%eth0    router
+not-an-address  broken
+10.0.0.1
+";
+
+    fn addresses(records: Option<Vec<Record>>) -> Vec<RData> {
+        records
+            .unwrap()
+            .into_iter()
+            .map(|record| record.rdata)
+            .collect()
+    }
+
+    #[test]
+    fn test_forward() {
+        let hosts = Hosts::parse(HOSTS);
+        assert_eq!(
+            addresses(hosts.lookup("localhost", RecordType::A, Class::IN)),
+            vec![RData::A(Ipv4Addr::LOCALHOST)]
+        );
+        assert_eq!(
+            addresses(hosts.lookup("localhost.", RecordType::AAAA, Class::IN)),
+            vec![RData::AAAA(Ipv6Addr::LOCALHOST)]
+        );
+        assert_eq!(
+            addresses(hosts.lookup("ip6-loopback", RecordType::AAAA, Class::IN)),
+            vec![RData::AAAA(Ipv6Addr::LOCALHOST)]
+        );
+        assert_eq!(
+            addresses(hosts.lookup("router", RecordType::AAAA, Class::IN)),
+            vec![RData::AAAA("fe80::1".parse().unwrap())]
+        );
+    }
+
+    #[test]
+    fn test_aliases_and_case() {
+        let hosts = Hosts::parse(HOSTS);
+        let records = hosts.lookup("THISHOST", RecordType::A, Class::IN).unwrap();
+        assert_eq!(
+            records,
+            vec![Record {
+                name: "thishost.".to_string(),
+                record_type: RecordType::A,
+                class: Class::IN,
+                ttl: 0,
+                rdata: RData::A(Ipv4Addr::new(127, 0, 1, 1)),
+            }]
+        );
+        assert!(hosts
+            .lookup("thishost.example.org", RecordType::A, Class::IN)
+            .is_some());
+    }
+
+    #[test]
+    fn test_reverse() {
+        let hosts = Hosts::parse(HOSTS);
+        assert_eq!(
+            addresses(hosts.lookup("10.1.168.192.in-addr.arpa.", RecordType::PTR, Class::IN)),
+            vec![RData::PTR("foo.example.org.".to_string())]
+        );
+        let ip6 = "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.ip6.arpa.";
+        assert_eq!(
+            addresses(hosts.lookup(ip6, RecordType::PTR, Class::IN)),
+            vec![RData::PTR("localhost.".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_not_found() {
+        let hosts = Hosts::parse(HOSTS);
+        assert!(hosts
+            .lookup("example.com", RecordType::A, Class::IN)
+            .is_none());
+        assert!(hosts.lookup("foo", RecordType::AAAA, Class::IN).is_none());
+        assert!(hosts.lookup("foo", RecordType::MX, Class::IN).is_none());
+        assert!(hosts.lookup("foo", RecordType::A, Class::CH).is_none());
+        assert!(hosts.lookup("broken", RecordType::A, Class::IN).is_none());
+        assert!(hosts
+            .lookup("9.9.9.9.in-addr.arpa", RecordType::PTR, Class::IN)
+            .is_none());
+    }
+
+    #[test]
+    fn test_reverse_name_to_ip() {
+        assert_eq!(
+            reverse_name_to_ip("4.3.2.1.IN-ADDR.ARPA."),
+            Some("1.2.3.4".parse().unwrap())
+        );
+        let ip6 = "b.a.9.8.7.6.5.0.4.0.0.0.3.0.0.0.2.0.0.0.1.0.0.0.0.0.0.0.1.2.3.4.ip6.arpa.";
+        assert_eq!(
+            reverse_name_to_ip(ip6),
+            Some("4321:0:1:2:3:4:567:89ab".parse().unwrap())
+        );
+        assert_eq!(reverse_name_to_ip("256.3.2.1.in-addr.arpa."), None);
+        assert_eq!(reverse_name_to_ip("3.2.1.in-addr.arpa."), None);
+        assert_eq!(reverse_name_to_ip("example.com."), None);
+        assert_eq!(reverse_name_to_ip("ab.0.ip6.arpa."), None);
+    }
+
+    #[test]
+    fn test_read() {
+        let path = std::env::temp_dir().join(format!("hosts.{}", std::process::id()));
+        fs::write(&path, "192.0.2.1 test\n").unwrap();
+        let hosts = Hosts::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(hosts.lookup("test", RecordType::A, Class::IN).is_some());
+        assert!(Hosts::read("/nonexistent/hosts").is_err());
+    }
+}
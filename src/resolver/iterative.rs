@@ -0,0 +1,678 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::client::{Client, Response, Transport, DEFAULT_ATTEMPTS, DEFAULT_TIMEOUT, DNS_PORT};
+use crate::error::DnsError;
+use crate::message::{message::Message, response_code::ResponseCode};
+use crate::rr::record::{fqdn, is_subdomain};
+use crate::rr::{rdata::RData, record_class::Class, record_type::RecordType};
+
+/// The root name servers and their addresses, from the root hints file
+/// published by IANA (https://www.internic.net/domain/named.root).
+pub const ROOT_HINTS: &[(&str, Ipv4Addr, Ipv6Addr)] = &[
+    (
+        "a.root-servers.net.",
+        Ipv4Addr::new(198, 41, 0, 4),
+        Ipv6Addr::new(0x2001, 0x503, 0xba3e, 0, 0, 0, 0x2, 0x30),
+    ),
+    (
+        "b.root-servers.net.",
+        Ipv4Addr::new(170, 247, 170, 2),
+        Ipv6Addr::new(0x2801, 0x1b8, 0x10, 0, 0, 0, 0, 0xb),
+    ),
+    (
+        "c.root-servers.net.",
+        Ipv4Addr::new(192, 33, 4, 12),
+        Ipv6Addr::new(0x2001, 0x500, 0x2, 0, 0, 0, 0, 0xc),
+    ),
+    (
+        "d.root-servers.net.",
+        Ipv4Addr::new(199, 7, 91, 13),
+        Ipv6Addr::new(0x2001, 0x500, 0x2d, 0, 0, 0, 0, 0xd),
+    ),
+    (
+        "e.root-servers.net.",
+        Ipv4Addr::new(192, 203, 230, 10),
+        Ipv6Addr::new(0x2001, 0x500, 0xa8, 0, 0, 0, 0, 0xe),
+    ),
+    (
+        "f.root-servers.net.",
+        Ipv4Addr::new(192, 5, 5, 241),
+        Ipv6Addr::new(0x2001, 0x500, 0x2f, 0, 0, 0, 0, 0xf),
+    ),
+    (
+        "g.root-servers.net.",
+        Ipv4Addr::new(192, 112, 36, 4),
+        Ipv6Addr::new(0x2001, 0x500, 0x12, 0, 0, 0, 0, 0xd0d),
+    ),
+    (
+        "h.root-servers.net.",
+        Ipv4Addr::new(198, 97, 190, 53),
+        Ipv6Addr::new(0x2001, 0x500, 0x1, 0, 0, 0, 0, 0x53),
+    ),
+    (
+        "i.root-servers.net.",
+        Ipv4Addr::new(192, 36, 148, 17),
+        Ipv6Addr::new(0x2001, 0x7fe, 0, 0, 0, 0, 0, 0x53),
+    ),
+    (
+        "j.root-servers.net.",
+        Ipv4Addr::new(192, 58, 128, 30),
+        Ipv6Addr::new(0x2001, 0x503, 0xc27, 0, 0, 0, 0x2, 0x30),
+    ),
+    (
+        "k.root-servers.net.",
+        Ipv4Addr::new(193, 0, 14, 129),
+        Ipv6Addr::new(0x2001, 0x7fd, 0, 0, 0, 0, 0, 0x1),
+    ),
+    (
+        "l.root-servers.net.",
+        Ipv4Addr::new(199, 7, 83, 42),
+        Ipv6Addr::new(0x2001, 0x500, 0x9f, 0, 0, 0, 0, 0x42),
+    ),
+    (
+        "m.root-servers.net.",
+        Ipv4Addr::new(202, 12, 27, 33),
+        Ipv6Addr::new(0x2001, 0xdc3, 0, 0, 0, 0, 0, 0x35),
+    ),
+];
+
+/// The most referrals followed for a single name, including those followed
+/// to find the addresses of name servers without glue.
+pub const MAX_REFERRALS: usize = 32;
+
+/// Resolves names by itself, starting from the root name servers and
+/// following referrals down to a name server authoritative for the name,
+/// instead of asking a recursive name server.
+#[derive(Debug, Clone)]
+pub struct Iterative {
+    roots: Vec<IpAddr>,
+    port: u16,
+    timeout: Duration,
+    attempts: usize,
+    transport: Transport,
+}
+
+impl Iterative {
+    /// Start from the [`ROOT_HINTS`], over IPv4 before IPv6.
+    pub fn new() -> Self {
+        let v4 = ROOT_HINTS.iter().map(|(_, v4, _)| IpAddr::V4(*v4));
+        let v6 = ROOT_HINTS.iter().map(|(_, _, v6)| IpAddr::V6(*v6));
+        Self {
+            roots: v4.chain(v6).collect(),
+            port: DNS_PORT,
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+            transport: Transport::Udp,
+        }
+    }
+
+    /// Start from the root name servers at `roots` instead of the root hints.
+    pub fn with_roots(mut self, roots: Vec<IpAddr>) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// Query every name server on `port` instead of 53.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// How long to wait for each response.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many times a query is sent to a name server before trying the next one.
+    pub fn with_attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Query the root name servers for `name`, then the name servers they
+    /// refer to, until one of them answers authoritatively or with an error.
+    ///
+    /// The addresses of the name servers are taken from the glue records of
+    /// the referral, or else resolved from the root too.
+    pub async fn resolve(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        class: Class,
+    ) -> Result<Response, DnsError> {
+        let mut referrals = 0;
+        self.resolve_from_root(&fqdn(name), record_type, class, &mut referrals)
+            .await
+    }
+
+    async fn resolve_from_root(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        class: Class,
+        referrals: &mut usize,
+    ) -> Result<Response, DnsError> {
+        let mut query = Message::new(0, name, record_type, class)?;
+        query.header.recursion_desired = false;
+        let mut zone = ".".to_string();
+        let mut servers: Vec<SocketAddr> = self
+            .roots
+            .iter()
+            .map(|ip| SocketAddr::new(*ip, self.port))
+            .collect();
+        loop {
+            let (response, referral) = self.query(&servers, &query, name, &zone).await?;
+            let Some((child, nameservers)) = referral else {
+                return Ok(response);
+            };
+            *referrals += 1;
+            if *referrals > MAX_REFERRALS {
+                return Err(DnsError::TooManyReferrals(MAX_REFERRALS));
+            }
+            debug!("{zone} referred {name} to {child} at {nameservers:?}");
+            servers = glue(&response.message, &nameservers, &zone)
+                .into_iter()
+                .map(|ip| SocketAddr::new(ip, self.port))
+                .collect();
+            if servers.is_empty() {
+                servers = self.resolve_addresses(&nameservers, referrals).await;
+            }
+            if servers.is_empty() {
+                return Err(DnsError::UnreachableZone(child));
+            }
+            zone = child;
+        }
+    }
+
+    /// The addresses of the first of `nameservers` that resolves, from the root.
+    async fn resolve_addresses(
+        &self,
+        nameservers: &[String],
+        referrals: &mut usize,
+    ) -> Vec<SocketAddr> {
+        for nameserver in nameservers {
+            for record_type in [RecordType::A, RecordType::AAAA] {
+                debug!("Resolving {nameserver} {record_type:?} without glue");
+                let resolved =
+                    Box::pin(self.resolve_from_root(nameserver, record_type, Class::IN, referrals))
+                        .await;
+                let response = match resolved {
+                    Ok(response) => response,
+                    Err(e @ DnsError::TooManyReferrals(_)) => {
+                        warn!("Could not resolve {nameserver}: {e}");
+                        return Vec::new();
+                    }
+                    Err(e) => {
+                        warn!("Could not resolve {nameserver}: {e}");
+                        continue;
+                    }
+                };
+                let addresses: Vec<SocketAddr> = response
+                    .message
+                    .answer
+                    .iter()
+                    .filter(|record| record.name.eq_ignore_ascii_case(nameserver))
+                    .filter_map(|record| address(&record.rdata))
+                    .map(|ip| SocketAddr::new(ip, self.port))
+                    .collect();
+                if !addresses.is_empty() {
+                    return addresses;
+                }
+            }
+        }
+        Vec::new()
+    }
+
+    /// Send `query` for `name` to `servers` of `zone` in turn, until one of
+    /// them answers authoritatively, with NXDOMAIN, or with a referral below
+    /// `zone`, which comes along. A server answering none of these is lame,
+    /// and the next one is tried.
+    ///
+    /// When none does, this is the last response or error received.
+    async fn query(
+        &self,
+        servers: &[SocketAddr],
+        query: &Message,
+        name: &str,
+        zone: &str,
+    ) -> Result<(Response, Option<(String, Vec<String>)>), DnsError> {
+        let mut last = Err(DnsError::Timeout(0));
+        for server in servers {
+            let client = Client::new(*server)
+                .with_transport(self.transport)
+                .with_timeout(self.timeout)
+                .with_attempts(self.attempts);
+            match client.query(query).await {
+                Ok(response) => {
+                    let resp_code = response.message.response_code();
+                    match resp_code {
+                        ResponseCode::NameError => return Ok((response, None)),
+                        ResponseCode::NoError if response.message.header.authoritative_answer => {
+                            return Ok((response, None));
+                        }
+                        ResponseCode::NoError => {
+                            if let Some(referral) = referral(&response.message, name, zone) {
+                                return Ok((response, Some(referral)));
+                            }
+                            warn!("{server} is lame for {zone}");
+                            last = Err(DnsError::NoReferral(zone.to_string()));
+                        }
+                        _ => {
+                            warn!("{server} answered {resp_code:?}");
+                            last = Ok((response, None));
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Query to {server} failed: {e}");
+                    last = Err(e);
+                }
+            }
+        }
+        last
+    }
+}
+
+impl Default for Iterative {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The zone `response` refers `name` to, below `zone`, and the names of its
+/// name servers, from the NS records of the authority section.
+fn referral(response: &Message, name: &str, zone: &str) -> Option<(String, Vec<String>)> {
+    let child = response
+        .authority
+        .iter()
+        .find_map(|record| match record.rdata {
+            RData::NS(_)
+                if is_subdomain(name, &record.name)
+                    && is_subdomain(&record.name, zone)
+                    && !is_subdomain(zone, &record.name) =>
+            {
+                Some(record.name.clone())
+            }
+            _ => None,
+        })?;
+    let nameservers = response
+        .authority
+        .iter()
+        .filter(|record| record.name.eq_ignore_ascii_case(&child))
+        .filter_map(|record| match &record.rdata {
+            RData::NS(nameserver) => Some(fqdn(nameserver)),
+            _ => None,
+        })
+        .collect();
+    Some((child, nameservers))
+}
+
+/// The addresses of `nameservers` in the additional section of `response`.
+/// Only those within `zone`, which the name server sending them is
+/// authoritative for, are trusted.
+fn glue(response: &Message, nameservers: &[String], zone: &str) -> Vec<IpAddr> {
+    response
+        .additional
+        .iter()
+        .filter(|record| {
+            is_subdomain(&record.name, zone)
+                && nameservers
+                    .iter()
+                    .any(|nameserver| record.name.eq_ignore_ascii_case(nameserver))
+        })
+        .filter_map(|record| address(&record.rdata))
+        .collect()
+}
+
+fn address(rdata: &RData) -> Option<IpAddr> {
+    match rdata {
+        RData::A(ip) => Some(IpAddr::V4(*ip)),
+        RData::AAAA(ip) => Some(IpAddr::V6(*ip)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests_iterative {
+    use super::*;
+    use crate::rr::record::Record;
+
+    fn record(name: &str, rdata: RData) -> Record {
+        Record {
+            name: name.to_string(),
+            record_type: rdata.record_type(),
+            class: Class::IN,
+            ttl: 3600,
+            rdata,
+        }
+    }
+
+    fn ns(name: &str, nameserver: &str) -> Record {
+        record(name, RData::NS(nameserver.to_string()))
+    }
+
+    fn a(name: &str, last_octet: u8) -> Record {
+        record(name, RData::A(Ipv4Addr::new(127, 0, 0, last_octet)))
+    }
+
+    #[test]
+    fn test_root_hints() {
+        assert_eq!(ROOT_HINTS.len(), 13);
+        let iterative = Iterative::new();
+        assert_eq!(iterative.roots.len(), 26);
+        assert_eq!(iterative.roots[0], IpAddr::V4(Ipv4Addr::new(198, 41, 0, 4)));
+    }
+
+    #[test]
+    fn test_referral() {
+        let mut response = Message::new(1, "www.example.com.", RecordType::A, Class::IN).unwrap();
+        response.authority = vec![
+            ns("org.", "ns.org."),
+            ns("com.", "a.gtld-servers.net."),
+            ns("com.", "b.gtld-servers.net"),
+        ];
+        response.additional = vec![
+            a("a.gtld-servers.net.", 1),
+            a("ns.org.", 2),
+            a("b.gtld-servers.net.", 3),
+        ];
+        let (child, nameservers) = referral(&response, "www.example.com.", ".").unwrap();
+        assert_eq!(child, "com.");
+        assert_eq!(
+            nameservers,
+            vec!["a.gtld-servers.net.", "b.gtld-servers.net."]
+        );
+        assert_eq!(
+            glue(&response, &nameservers, "."),
+            vec![
+                IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                IpAddr::V4(Ipv4Addr::new(127, 0, 0, 3))
+            ]
+        );
+        // glue out of the zone of the referring server is not trusted
+        assert!(glue(&response, &nameservers, "org.").is_empty());
+
+        // a referral to the same zone or above does not get any closer
+        assert!(referral(&response, "www.example.com.", "com.").is_none());
+        assert!(referral(&response, "www.example.net.", ".").is_none());
+    }
+
+    /// Name servers listening on 127.0.0.1 to 127.0.0.5 with the same port,
+    /// as glue records only carry addresses. Only Linux has every 127.0.0.0/8
+    /// address on the loopback interface without further setup.
+    #[cfg(target_os = "linux")]
+    mod tests_loopback {
+        use super::*;
+        use crate::message::message::MAX_UDP_BYTES;
+        use crate::resolver::{config::ResolverConfig, Resolver, Source};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use tokio::net::UdpSocket;
+
+        /// An authoritative name server for `zone` at `127.0.0.<last_octet>:port`,
+        /// answering from `records`. Names below the NS records of other zones
+        /// are referred to them, with their glue. Returns a count of the queries
+        /// it received.
+        async fn authority(
+            last_octet: u8,
+            port: u16,
+            zone: &'static str,
+            records: Vec<Record>,
+        ) -> Arc<AtomicUsize> {
+            let socket = UdpSocket::bind((Ipv4Addr::new(127, 0, 0, last_octet), port))
+                .await
+                .unwrap();
+            let received = Arc::new(AtomicUsize::new(0));
+            let count = received.clone();
+            tokio::spawn(async move {
+                let mut buf = vec![0; MAX_UDP_BYTES];
+                loop {
+                    let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                    count.fetch_add(1, Ordering::SeqCst);
+                    let mut response = Message::deserialize(&buf[..len]).unwrap();
+                    response.header.is_response = true;
+                    let question = response.question[0].clone();
+                    let cut = records.iter().find(|record| {
+                        record.record_type == RecordType::NS
+                            && record.name != zone
+                            && is_subdomain(&question.name, &record.name)
+                    });
+                    if let Some(cut) = cut {
+                        response.authority = records
+                            .iter()
+                            .filter(|record| record.name == cut.name)
+                            .cloned()
+                            .collect();
+                        response.additional = records
+                            .iter()
+                            .filter(|record| {
+                                response.authority.iter().any(|ns| {
+                                    ns.rdata == RData::NS(record.name.clone())
+                                        && record.record_type == RecordType::A
+                                })
+                            })
+                            .cloned()
+                            .collect();
+                    } else {
+                        response.header.authoritative_answer = true;
+                        response.answer = records
+                            .iter()
+                            .filter(|record| {
+                                record.name == question.name
+                                    && record.record_type == question.record_type
+                            })
+                            .cloned()
+                            .collect();
+                        if !records.iter().any(|record| record.name == question.name) {
+                            response.header.resp_code = ResponseCode::NameError;
+                        }
+                    }
+                    let _ = socket.send_to(&response.as_vec().unwrap(), from).await;
+                }
+            });
+            received
+        }
+
+        /// A name server at `127.0.0.<last_octet>:port` which is not authoritative
+        /// for anything, answering with empty sections.
+        async fn lame(last_octet: u8, port: u16) -> Arc<AtomicUsize> {
+            let socket = UdpSocket::bind((Ipv4Addr::new(127, 0, 0, last_octet), port))
+                .await
+                .unwrap();
+            let received = Arc::new(AtomicUsize::new(0));
+            let count = received.clone();
+            tokio::spawn(async move {
+                let mut buf = vec![0; MAX_UDP_BYTES];
+                loop {
+                    let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                    count.fetch_add(1, Ordering::SeqCst);
+                    let mut response = Message::deserialize(&buf[..len]).unwrap();
+                    response.header.is_response = true;
+                    let _ = socket.send_to(&response.as_vec().unwrap(), from).await;
+                }
+            });
+            received
+        }
+
+        /// The root at 127.0.0.1, delegating `example.` with glue and `net.`
+        /// The `sub.example.` zone has a name server in `net.` without glue.
+        async fn hierarchy() -> (Iterative, Vec<Arc<AtomicUsize>>) {
+            let port = UdpSocket::bind("127.0.0.1:0")
+                .await
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let root = authority(
+                1,
+                port,
+                ".",
+                vec![
+                    ns("example.", "ns.example."),
+                    a("ns.example.", 2),
+                    ns("net.", "ns.net."),
+                    a("ns.net.", 3),
+                ],
+            )
+            .await;
+            let example = authority(
+                2,
+                port,
+                "example.",
+                vec![
+                    ns("example.", "ns.example."),
+                    a("ns.example.", 2),
+                    a("www.example.", 10),
+                    ns("sub.example.", "ns.other.net."),
+                    ns("broken.example.", "ns.nowhere.net."),
+                ],
+            )
+            .await;
+            let net = authority(3, port, "net.", vec![a("ns.other.net.", 4)]).await;
+            let sub = authority(4, port, "sub.example.", vec![a("www.sub.example.", 20)]).await;
+            let iterative = Iterative::new()
+                .with_roots(vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))])
+                .with_port(port)
+                .with_timeout(Duration::from_millis(500))
+                .with_attempts(1);
+            (iterative, vec![root, example, net, sub])
+        }
+
+        #[tokio::test]
+        async fn test_resolve_with_glue() {
+            let (iterative, received) = hierarchy().await;
+            let response = iterative
+                .resolve("www.example", RecordType::A, Class::IN)
+                .await
+                .unwrap();
+            assert!(response.message.header.authoritative_answer);
+            assert_eq!(response.message.answer, vec![a("www.example.", 10)]);
+            assert_eq!(
+                response.server.ip(),
+                IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))
+            );
+            let received: Vec<usize> = received
+                .iter()
+                .map(|count| count.load(Ordering::SeqCst))
+                .collect();
+            assert_eq!(received, vec![1, 1, 0, 0]);
+        }
+
+        #[tokio::test]
+        async fn test_resolve_without_glue() {
+            let (iterative, received) = hierarchy().await;
+            let response = iterative
+                .resolve("www.sub.example.", RecordType::A, Class::IN)
+                .await
+                .unwrap();
+            assert_eq!(response.message.answer, vec![a("www.sub.example.", 20)]);
+            assert_eq!(
+                response.server.ip(),
+                IpAddr::V4(Ipv4Addr::new(127, 0, 0, 4))
+            );
+            // the name server of `sub.example.` is resolved from the root
+            assert_eq!(received[2].load(Ordering::SeqCst), 1);
+        }
+
+        #[tokio::test]
+        async fn test_name_error() {
+            let (iterative, _) = hierarchy().await;
+            let response = iterative
+                .resolve("nx.example.", RecordType::A, Class::IN)
+                .await
+                .unwrap();
+            assert_eq!(response.message.response_code(), ResponseCode::NameError);
+            assert!(response.message.header.authoritative_answer);
+        }
+
+        #[tokio::test]
+        async fn test_lame_server() {
+            let port = UdpSocket::bind("127.0.0.1:0")
+                .await
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            authority(
+                1,
+                port,
+                ".",
+                vec![
+                    ns("example.", "lame.example."),
+                    a("lame.example.", 5),
+                    ns("example.", "ns.example."),
+                    a("ns.example.", 2),
+                ],
+            )
+            .await;
+            let lame = lame(5, port).await;
+            authority(
+                2,
+                port,
+                "example.",
+                vec![ns("example.", "ns.example."), a("www.example.", 10)],
+            )
+            .await;
+            let iterative = Iterative::new()
+                .with_roots(vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))])
+                .with_port(port)
+                .with_timeout(Duration::from_millis(500))
+                .with_attempts(1);
+            let response = iterative
+                .resolve("www.example.", RecordType::A, Class::IN)
+                .await
+                .unwrap();
+            assert_eq!(response.message.answer, vec![a("www.example.", 10)]);
+            assert_eq!(lame.load(Ordering::SeqCst), 1);
+
+            // every server is lame
+            let iterative = iterative.with_roots(vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 5))]);
+            let err = iterative
+                .resolve("www.example.", RecordType::A, Class::IN)
+                .await
+                .unwrap_err();
+            assert_eq!(err, DnsError::NoReferral(".".to_string()));
+        }
+
+        #[tokio::test]
+        async fn test_unreachable_zone() {
+            let (iterative, _) = hierarchy().await;
+            let err = iterative
+                .resolve("www.broken.example.", RecordType::A, Class::IN)
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err,
+                DnsError::UnreachableZone("broken.example.".to_string())
+            );
+        }
+
+        #[tokio::test]
+        async fn test_resolver() {
+            let (iterative, _) = hierarchy().await;
+            let config = ResolverConfig {
+                // never queried
+                nameservers: vec!["192.0.2.53:53".parse().unwrap()],
+                ..ResolverConfig::default()
+            };
+            let resolver = Resolver::new(config).unwrap().with_iterative(iterative);
+            let lookup = resolver
+                .lookup("www.sub.example.", RecordType::A, Class::IN)
+                .await
+                .unwrap();
+            assert_eq!(lookup.records, vec![a("www.sub.example.", 20)]);
+            assert!(
+                matches!(lookup.source, Source::Server(server, _) if server.ip().is_loopback())
+            );
+        }
+    }
+}
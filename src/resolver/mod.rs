@@ -0,0 +1,922 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use self::cache::{Cache, CacheStats, Cached};
+use self::chain::Chain;
+use self::config::{ResolverConfig, Strategy};
+use self::hosts::Hosts;
+use self::iterative::Iterative;
+use crate::client::{Client, Response, Transfer, Transport};
+use crate::error::DnsError;
+use crate::message::{edns::Edns, message::Message, response_code::ResponseCode};
+use crate::rr::record::{fqdn, Record};
+use crate::rr::{record_class::Class, record_type::RecordType};
+
+pub mod cache;
+pub mod chain;
+pub mod config;
+pub mod hosts;
+pub mod iterative;
+pub mod resolv_conf;
+pub mod search;
+
+/// What is known of a name server from the previous queries.
+#[derive(Debug, Clone, Copy, Default)]
+struct ServerState {
+    /// Smoothed round trip time, as in RFC 6298.
+    srtt: Option<Duration>,
+    /// When the name server can be queried again after answering SERVFAIL or REFUSED.
+    cooldown_until: Option<Instant>,
+}
+
+/// Where the answer to a lookup comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    /// The hosts file, without querying any name server.
+    Hosts,
+    /// The cache of previous answers, with their TTL decremented.
+    Cache,
+    /// A name server, over the given transport.
+    Server(SocketAddr, Transport),
+}
+
+/// The outcome of looking a name up through the search list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lookup {
+    /// The response to the first name with data. When none has any, the
+    /// first NOERROR response, or else the last one. When the name is an
+    /// alias, this is the response the aliases were followed to.
+    pub message: Message,
+    pub source: Source,
+    /// Every name looked up, in order, the last being the one `message` is
+    /// for when it has data.
+    pub candidates: Vec<String>,
+    /// The CNAME and DNAME records followed from the candidate to the records.
+    pub aliases: Vec<Record>,
+    /// The records of the requested type, at the end of the aliases.
+    pub records: Vec<Record>,
+}
+
+impl Lookup {
+    fn new(message: Message, source: Source, candidates: Vec<String>, chain: Chain) -> Self {
+        Self {
+            message,
+            source,
+            candidates,
+            aliases: chain.aliases,
+            records: chain.records,
+        }
+    }
+}
+
+/// A message answering a question with what is known of it, as if a name
+/// server had sent it.
+fn answer_message(
+    name: &str,
+    record_type: RecordType,
+    class: Class,
+    answer: Cached,
+) -> Result<Message, DnsError> {
+    let mut message = Message::new(0, name, record_type, class)?;
+    message.header.is_response = true;
+    message.header.recursion_available = true;
+    match answer {
+        Cached::Records(records) => message.set_answer(records),
+        Cached::NoData(soa) => message.set_authority(vec![soa]),
+        Cached::NameError(soa) => {
+            message.header.resp_code = ResponseCode::NameError;
+            message.set_authority(vec![soa]);
+        }
+    }
+    Ok(message)
+}
+
+/// Sends queries to a list of name servers, failing over from one to the next.
+#[derive(Debug)]
+pub struct Resolver {
+    config: ResolverConfig,
+    hosts: Hosts,
+    cache: Option<Mutex<Cache>>,
+    /// Resolves names from the root instead of querying the name servers, when set.
+    iterative: Option<Iterative>,
+    clients: Vec<Client>,
+    state: Mutex<Vec<ServerState>>,
+    /// Where the next query starts in the round robin strategy.
+    next: AtomicUsize,
+}
+
+impl Resolver {
+    pub fn new(config: ResolverConfig) -> Result<Self, DnsError> {
+        if config.nameservers.is_empty() {
+            return Err(DnsError::InvalidConfig(
+                "No name server to query".to_string(),
+            ));
+        }
+        let clients = config
+            .nameservers
+            .iter()
+            .map(|server| {
+                Client::new(*server)
+                    .with_transport(config.transport)
+                    .with_retry_truncated(config.retry_truncated)
+                    .with_timeout(config.timeout)
+                    .with_attempts(config.attempts)
+            })
+            .collect();
+        Ok(Self {
+            state: Mutex::new(vec![ServerState::default(); config.nameservers.len()]),
+            config,
+            hosts: Hosts::default(),
+            cache: None,
+            iterative: None,
+            clients,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Answer lookups from `hosts` when it has the name, before querying name servers.
+    pub fn with_hosts(mut self, hosts: Hosts) -> Self {
+        self.hosts = hosts;
+        self
+    }
+
+    /// Keep answers in `cache`, and answer lookups from it while their TTL lasts.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(Mutex::new(cache));
+        self
+    }
+
+    /// Resolve names with `iterative`, from the root name servers down,
+    /// instead of querying the configured name servers.
+    pub fn with_iterative(mut self, iterative: Iterative) -> Self {
+        self.iterative = Some(iterative);
+        self
+    }
+
+    /// How often lookups were answered from the cache, when there is one.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache
+            .as_ref()
+            .map(|cache| cache.lock().expect("cache lock is poisoned").stats())
+    }
+
+    pub fn config(&self) -> &ResolverConfig {
+        &self.config
+    }
+
+    /// Look `name` up in the hosts file, then in the cache or with the name
+    /// servers, expanding it with the search list when it is not fully
+    /// qualified, until a candidate gets a NOERROR response with records of
+    /// `record_type`, following its CNAME and DNAME records to them.
+    ///
+    /// When every query fails, this is the last error. Zone transfers are
+    /// not lookups, see [`Resolver::transfer`].
+    pub async fn lookup(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        class: Class,
+    ) -> Result<Lookup, DnsError> {
+        if matches!(record_type, RecordType::AXFR | RecordType::IXFR) {
+            return Err(DnsError::ZoneTransfer(record_type));
+        }
+        if let Some(records) = self.hosts.lookup(name, record_type, class) {
+            debug!("Found {name} in the hosts file");
+            let mut chain = Chain::new(name);
+            chain.records = records.clone();
+            return Ok(Lookup::new(
+                answer_message(name, record_type, class, Cached::Records(records))?,
+                Source::Hosts,
+                vec![fqdn(name)],
+                chain,
+            ));
+        }
+
+        let mut candidates = Vec::new();
+        let mut nodata: Option<(Message, Source, Chain)> = None;
+        let mut last = Err(DnsError::Timeout(0));
+        for candidate in search::candidates(name, &self.config.search, self.config.ndots) {
+            debug!("Looking up {candidate}");
+            candidates.push(candidate.clone());
+            match self.resolve(&candidate, record_type, class).await {
+                Ok((message, source, chain)) => {
+                    if message.response_code() == ResponseCode::NoError {
+                        if !chain.records.is_empty() {
+                            return Ok(Lookup::new(message, source, candidates, chain));
+                        }
+                        nodata.get_or_insert_with(|| (message.clone(), source, chain.clone()));
+                    }
+                    last = Ok((message, source, chain));
+                }
+                Err(e) => last = Err(e),
+            }
+        }
+        let (message, source, chain) = match nodata {
+            Some(nodata) => nodata,
+            None => last?,
+        };
+        Ok(Lookup::new(message, source, candidates, chain))
+    }
+
+    /// Transfer the zone `name` of `class` (AXFR) from the name servers in the
+    /// order of the strategy, until one of them does not refuse it. The hosts
+    /// file, the search list and the cache do not apply.
+    ///
+    /// When none allows it, this is the last response or error received.
+    pub async fn transfer(&self, name: &str, class: Class) -> Result<Transfer, DnsError> {
+        let query = Message::new(0, &fqdn(name), RecordType::AXFR, class)?;
+        let mut last = Err(DnsError::Timeout(0));
+        for ix in self.order(Instant::now()) {
+            let client = &self.clients[ix];
+            match client.transfer(&query).await {
+                Ok(transfer) => {
+                    let resp_code = transfer.messages[0].response_code();
+                    if resp_code == ResponseCode::NoError {
+                        return Ok(transfer);
+                    }
+                    warn!(
+                        "{} answered {:?} to the transfer",
+                        client.server(),
+                        resp_code
+                    );
+                    last = Ok(transfer);
+                }
+                Err(e) => {
+                    warn!("Transfer from {} failed: {e}", client.server());
+                    last = Err(e);
+                }
+            }
+        }
+        last
+    }
+
+    /// Look the fully qualified `name` up in the cache or with the name
+    /// servers, then look up the names its aliases lead to when the response
+    /// does not have their records, nor says that they have none.
+    async fn resolve(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        class: Class,
+    ) -> Result<(Message, Source, Chain), DnsError> {
+        let mut chain = Chain::new(name);
+        loop {
+            let (message, source) = self.resolve_name(&chain.name, record_type, class).await?;
+            let followed = chain.aliases.len();
+            chain.follow(record_type, &message.answer)?;
+            let negative = message
+                .authority
+                .iter()
+                .any(|record| record.record_type == RecordType::SOA);
+            if message.response_code() != ResponseCode::NoError
+                || !chain.records.is_empty()
+                || chain.aliases.len() == followed
+                || negative
+            {
+                return Ok((message, source, chain));
+            }
+            debug!("Following the aliases of {name} to {}", chain.name);
+        }
+    }
+
+    /// Look the fully qualified `name` up in the cache, or else with the name
+    /// servers, caching their response.
+    async fn resolve_name(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        class: Class,
+    ) -> Result<(Message, Source), DnsError> {
+        let cached = self.cached(|cache| cache.get(name, record_type, class));
+        if let Some(answer) = cached.flatten() {
+            debug!("Found {name} in the cache");
+            let message = answer_message(name, record_type, class, answer)?;
+            return Ok((message, Source::Cache));
+        }
+        let response = match &self.iterative {
+            Some(iterative) => iterative.resolve(name, record_type, class).await?,
+            None => {
+                let mut query = Message::new(0, name, record_type, class)?;
+                query.header.recursion_desired = self.config.recursion_desired;
+                if self.config.edns0 {
+                    query.set_edns(Some(Edns::default()));
+                }
+                self.query(&query).await?
+            }
+        };
+        self.cached(|cache| cache.insert_response(name, record_type, class, &response.message));
+        let source = Source::Server(response.server, response.transport);
+        Ok((response.message, source))
+    }
+
+    /// Send `query` to the name servers in the order of the strategy, until
+    /// one of them answers with something else than SERVFAIL or REFUSED.
+    ///
+    /// When none does, this is the last response or error received.
+    pub async fn query(&self, query: &Message) -> Result<Response, DnsError> {
+        let mut last = Err(DnsError::Timeout(0));
+        for ix in self.order(Instant::now()) {
+            let client = &self.clients[ix];
+            let started = Instant::now();
+            let response = client.query(query).await;
+            let elapsed = started.elapsed();
+            match response {
+                Ok(response) => {
+                    self.update(ix, |state| state.record_rtt(elapsed));
+                    let resp_code = response.message.response_code();
+                    if !matches!(
+                        resp_code,
+                        ResponseCode::ServerFailure | ResponseCode::Refused
+                    ) {
+                        return Ok(response);
+                    }
+                    warn!(
+                        "{} answered {:?}, cooling it down",
+                        client.server(),
+                        resp_code
+                    );
+                    let until = Instant::now() + self.config.cooldown;
+                    self.update(ix, |state| state.cooldown_until = Some(until));
+                    last = Ok(response);
+                }
+                Err(e) => {
+                    // A failure counts as slow as a timeout, so the server is tried last.
+                    let penalty = elapsed.max(self.config.timeout);
+                    self.update(ix, |state| state.record_rtt(penalty));
+                    warn!("Query to {} failed: {e}", client.server());
+                    last = Err(e);
+                }
+            }
+        }
+        last
+    }
+
+    /// The indices of the name servers, in the order they are tried in at `now`.
+    /// Those cooling down are skipped, unless every one of them is.
+    fn order(&self, now: Instant) -> Vec<usize> {
+        let count = self.clients.len();
+        let state = self.state.lock().expect("state lock is poisoned");
+        let mut order: Vec<usize> = match self.config.strategy {
+            Strategy::Sequential => (0..count).collect(),
+            Strategy::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed) % count;
+                (0..count).map(|ix| (start + ix) % count).collect()
+            }
+            Strategy::Fastest => {
+                let mut order: Vec<usize> = (0..count).collect();
+                // Name servers never queried come first, so that they get measured.
+                order.sort_by_key(|ix| state[*ix].srtt.unwrap_or_default());
+                order
+            }
+        };
+        let available: Vec<usize> = order
+            .iter()
+            .copied()
+            .filter(|ix| state[*ix].cooldown_until.is_none_or(|until| until <= now))
+            .collect();
+        if !available.is_empty() {
+            order = available;
+        }
+        debug!(
+            "Trying name servers {:?}",
+            order
+                .iter()
+                .map(|ix| self.config.nameservers[*ix])
+                .collect::<Vec<SocketAddr>>()
+        );
+        order
+    }
+
+    /// Run `f` on the cache, when there is one.
+    fn cached<T>(&self, f: impl FnOnce(&mut Cache) -> T) -> Option<T> {
+        self.cache
+            .as_ref()
+            .map(|cache| f(&mut cache.lock().expect("cache lock is poisoned")))
+    }
+
+    fn update(&self, ix: usize, f: impl FnOnce(&mut ServerState)) {
+        f(&mut self.state.lock().expect("state lock is poisoned")[ix]);
+    }
+}
+
+impl ServerState {
+    /// Add a round trip time sample, with a weight of 1/8 (RFC 6298 2.3).
+    fn record_rtt(&mut self, rtt: Duration) {
+        self.srtt = Some(match self.srtt {
+            None => rtt,
+            Some(srtt) => srtt * 7 / 8 + rtt / 8,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests_resolver {
+    use super::*;
+    use crate::client::tcp;
+    use crate::message::message::MAX_UDP_BYTES;
+    use crate::rr::{rdata::RData, record::Record};
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, UdpSocket};
+
+    /// A name server on a local port, answering every query with `resp_code`,
+    /// or dropping them when `None`. Returns its address and a count of the
+    /// queries it received.
+    async fn server(resp_code: Option<ResponseCode>) -> (SocketAddr, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let received = Arc::new(AtomicUsize::new(0));
+        let count = received.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0; MAX_UDP_BYTES];
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                count.fetch_add(1, Ordering::SeqCst);
+                let Some(resp_code) = resp_code else {
+                    continue;
+                };
+                let mut response = Message::deserialize(&buf[..len]).unwrap();
+                response.header.is_response = true;
+                response.header.resp_code = resp_code;
+                let _ = socket.send_to(&response.as_vec().unwrap(), from).await;
+            }
+        });
+        (addr, received)
+    }
+
+    fn new_resolver(nameservers: Vec<SocketAddr>, strategy: Strategy) -> Resolver {
+        Resolver::new(ResolverConfig {
+            nameservers,
+            strategy,
+            timeout: Duration::from_millis(100),
+            attempts: 1,
+            ..ResolverConfig::default()
+        })
+        .unwrap()
+    }
+
+    fn query() -> Message {
+        Message::new(0, "example.com.", RecordType::A, Class::IN).unwrap()
+    }
+
+    fn addrs(count: u16) -> Vec<SocketAddr> {
+        (1..=count)
+            .map(|port| SocketAddr::from(([127, 0, 0, 1], port)))
+            .collect()
+    }
+
+    /// A name server on a local port answering with an A record for
+    /// `www.b.example.`, NOERROR without answers for `www.a.example.` and
+    /// NXDOMAIN for every other name.
+    async fn zone_server() -> (SocketAddr, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let received = Arc::new(AtomicUsize::new(0));
+        let count = received.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0; MAX_UDP_BYTES];
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                count.fetch_add(1, Ordering::SeqCst);
+                let mut response = Message::deserialize(&buf[..len]).unwrap();
+                response.header.is_response = true;
+                let name = response.question[0].name.clone();
+                match name.as_str() {
+                    "www.b.example." => response.answer.push(Record {
+                        name,
+                        record_type: RecordType::A,
+                        class: Class::IN,
+                        ttl: 60,
+                        rdata: RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+                    }),
+                    "www.a.example." => response.authority.push(soa()),
+                    // aliases whose records need another query
+                    "alias.a.example." => response.answer.push(cname(&name, "www.b.example.")),
+                    "loop.a.example." => response.answer.push(cname(&name, "loop.b.example.")),
+                    "loop.b.example." => response.answer.push(cname(&name, "loop.a.example.")),
+                    _ => {
+                        response.header.resp_code = ResponseCode::NameError;
+                        response.authority.push(soa());
+                    }
+                }
+                let _ = socket.send_to(&response.as_vec().unwrap(), from).await;
+            }
+        });
+        (addr, received)
+    }
+
+    fn cname(name: &str, target: &str) -> Record {
+        Record {
+            name: name.to_string(),
+            record_type: RecordType::CNAME,
+            class: Class::IN,
+            ttl: 60,
+            rdata: RData::CNAME(target.to_string()),
+        }
+    }
+
+    /// The SOA record of the `example.` zone, with a negative TTL of 30 seconds.
+    fn soa() -> Record {
+        Record {
+            name: "example.".to_string(),
+            record_type: RecordType::SOA,
+            class: Class::IN,
+            ttl: 300,
+            rdata: RData::SOA {
+                mname: "ns.example.".to_string(),
+                rname: "hostmaster.example.".to_string(),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 30,
+            },
+        }
+    }
+
+    fn search_resolver(nameserver: SocketAddr, search: &[&str]) -> Resolver {
+        Resolver::new(ResolverConfig {
+            search: search.iter().map(|domain| domain.to_string()).collect(),
+            ..new_resolver(vec![nameserver], Strategy::Sequential).config
+        })
+        .unwrap()
+    }
+
+    /// A name server on a local TCP port answering zone transfers with the
+    /// SOA record of `example.` twice, which is the smallest complete zone,
+    /// or refusing them when `refuse` is set.
+    async fn transfer_server(refuse: bool) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let query = tcp::read_message(&mut stream).await.unwrap();
+                let mut response = Message::deserialize(&query).unwrap();
+                response.header.is_response = true;
+                if refuse {
+                    response.header.resp_code = ResponseCode::Refused;
+                } else if response.question[0].name == "example." {
+                    response.set_answer(vec![soa(), soa()]);
+                }
+                let _ = tcp::write_message(&mut stream, &response.as_vec().unwrap()).await;
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_transfer() {
+        let nameservers = vec![transfer_server(true).await, transfer_server(false).await];
+        let resolver = Resolver::new(ResolverConfig {
+            search: vec!["a.example".to_string()],
+            ..new_resolver(nameservers.clone(), Strategy::Sequential).config
+        })
+        .unwrap();
+        let transfer = resolver.transfer("example", Class::IN).await.unwrap();
+        assert_eq!(transfer.server, nameservers[1]);
+        assert_eq!(transfer.records().count(), 2);
+
+        assert_eq!(
+            resolver
+                .lookup("example", RecordType::AXFR, Class::IN)
+                .await
+                .unwrap_err(),
+            DnsError::ZoneTransfer(RecordType::AXFR)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_search_list() {
+        let resolver = search_resolver(
+            zone_server().await.0,
+            &["c.example", "a.example", "b.example"],
+        );
+        let lookup = resolver
+            .lookup("www", RecordType::A, Class::IN)
+            .await
+            .unwrap();
+        assert_eq!(
+            lookup.candidates,
+            vec!["www.c.example.", "www.a.example.", "www.b.example."]
+        );
+        assert_eq!(lookup.message.answer[0].name, "www.b.example.");
+    }
+
+    #[tokio::test]
+    async fn test_lookup_nodata() {
+        let resolver = search_resolver(zone_server().await.0, &["c.example", "a.example"]);
+        let lookup = resolver
+            .lookup("www", RecordType::A, Class::IN)
+            .await
+            .unwrap();
+        assert_eq!(
+            lookup.candidates,
+            vec!["www.c.example.", "www.a.example.", "www."]
+        );
+        // NODATA is more useful than the NXDOMAIN for `www.`
+        assert_eq!(lookup.message.question[0].name, "www.a.example.");
+        assert_eq!(lookup.message.response_code(), ResponseCode::NoError);
+        assert!(lookup.message.answer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_lookup_nxdomain() {
+        let resolver = search_resolver(zone_server().await.0, &["c.example"]);
+        let lookup = resolver
+            .lookup("www.d.example", RecordType::A, Class::IN)
+            .await
+            .unwrap();
+        assert_eq!(
+            lookup.candidates,
+            vec!["www.d.example.", "www.d.example.c.example."]
+        );
+        assert_eq!(lookup.message.response_code(), ResponseCode::NameError);
+
+        let lookup = resolver
+            .lookup("www.b.example.", RecordType::A, Class::IN)
+            .await
+            .unwrap();
+        assert_eq!(lookup.candidates, vec!["www.b.example."]);
+        assert_eq!(lookup.message.answer.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_hosts_first() {
+        let (nameserver, received) = server(Some(ResponseCode::NoError)).await;
+        let hosts = Hosts::parse("192.0.2.7 www.example.com www\n");
+        let resolver = search_resolver(nameserver, &["example.com"]).with_hosts(hosts);
+
+        let lookup = resolver
+            .lookup("www", RecordType::A, Class::IN)
+            .await
+            .unwrap();
+        assert_eq!(lookup.source, Source::Hosts);
+        assert_eq!(lookup.candidates, vec!["www."]);
+        assert_eq!(
+            lookup.message.answer[0].rdata,
+            RData::A(Ipv4Addr::new(192, 0, 2, 7))
+        );
+        let lookup = resolver
+            .lookup("7.2.0.192.in-addr.arpa.", RecordType::PTR, Class::IN)
+            .await
+            .unwrap();
+        assert_eq!(
+            lookup.message.answer[0].rdata,
+            RData::PTR("www.example.com.".to_string())
+        );
+        assert_eq!(received.load(Ordering::SeqCst), 0);
+
+        // not in the hosts file
+        let lookup = resolver
+            .lookup("www", RecordType::MX, Class::IN)
+            .await
+            .unwrap();
+        assert_eq!(lookup.source, Source::Server(nameserver, Transport::Udp));
+        assert_eq!(lookup.candidates, vec!["www.example.com.", "www."]);
+        assert_eq!(received.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_cached() {
+        let resolver =
+            search_resolver(zone_server().await.0, &["b.example"]).with_cache(Cache::default());
+        assert_eq!(resolver.cache_stats(), Some(CacheStats::default()));
+
+        let lookup = resolver
+            .lookup("www", RecordType::A, Class::IN)
+            .await
+            .unwrap();
+        assert!(matches!(lookup.source, Source::Server(..)));
+        let lookup = resolver
+            .lookup("www", RecordType::A, Class::IN)
+            .await
+            .unwrap();
+        assert_eq!(lookup.source, Source::Cache);
+        assert_eq!(lookup.candidates, vec!["www.b.example."]);
+        assert_eq!(lookup.message.question[0].name, "www.b.example.");
+        assert_eq!(
+            lookup.message.answer[0].rdata,
+            RData::A(Ipv4Addr::new(192, 0, 2, 1))
+        );
+        assert_eq!(
+            resolver.cache_stats(),
+            Some(CacheStats { hits: 1, misses: 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_answer_counts() {
+        let counts = |message: &Message| {
+            let header = &message.header;
+            (
+                header.question_count,
+                header.answer_count,
+                header.name_server_count,
+                header.additional_records_count,
+            )
+        };
+        let hosts = Hosts::parse("192.0.2.7 www.a.example\n");
+        let resolver = search_resolver(zone_server().await.0, &["a.example"])
+            .with_hosts(hosts)
+            .with_cache(Cache::default());
+
+        let lookup = resolver
+            .lookup("www.a.example.", RecordType::A, Class::IN)
+            .await
+            .unwrap();
+        assert_eq!(lookup.source, Source::Hosts);
+        assert_eq!(counts(&lookup.message), (1, 1, 0, 0));
+
+        for _ in 0..2 {
+            let lookup = resolver
+                .lookup("www.b.example.", RecordType::A, Class::IN)
+                .await
+                .unwrap();
+            assert_eq!(counts(&lookup.message), (1, 1, 0, 0));
+        }
+        for _ in 0..2 {
+            let lookup = resolver
+                .lookup("www.c.example.", RecordType::A, Class::IN)
+                .await
+                .unwrap();
+            assert_eq!(counts(&lookup.message), (1, 0, 1, 0));
+        }
+        assert_eq!(
+            resolver.cache_stats(),
+            Some(CacheStats { hits: 2, misses: 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_negative_cached() {
+        let (nameserver, received) = zone_server().await;
+        let resolver =
+            search_resolver(nameserver, &["c.example", "a.example"]).with_cache(Cache::default());
+        for _ in 0..3 {
+            let lookup = resolver
+                .lookup("www", RecordType::A, Class::IN)
+                .await
+                .unwrap();
+            assert_eq!(lookup.message.response_code(), ResponseCode::NoError);
+            assert_eq!(lookup.message.question[0].name, "www.a.example.");
+            assert_eq!(lookup.message.authority[0].name, "example.");
+        }
+        assert_eq!(received.load(Ordering::SeqCst), 3);
+
+        // the name does not exist whatever the type
+        let lookup = resolver
+            .lookup("www.c.example.", RecordType::MX, Class::IN)
+            .await
+            .unwrap();
+        assert_eq!(lookup.source, Source::Cache);
+        assert_eq!(lookup.message.response_code(), ResponseCode::NameError);
+        assert_eq!(received.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_follows_aliases() {
+        let (nameserver, received) = zone_server().await;
+        let resolver = search_resolver(nameserver, &["a.example"]).with_cache(Cache::default());
+        for _ in 0..2 {
+            let lookup = resolver
+                .lookup("alias", RecordType::A, Class::IN)
+                .await
+                .unwrap();
+            assert_eq!(lookup.candidates, vec!["alias.a.example."]);
+            assert_eq!(lookup.aliases.len(), 1);
+            assert_eq!(
+                lookup.aliases[0].rdata,
+                RData::CNAME("www.b.example.".to_string())
+            );
+            assert_eq!(lookup.records.len(), 1);
+            assert_eq!(lookup.records[0].name, "www.b.example.");
+            assert_eq!(lookup.message.question[0].name, "www.b.example.");
+        }
+        assert_eq!(received.load(Ordering::SeqCst), 2);
+
+        let err = resolver
+            .lookup("loop.a.example.", RecordType::A, Class::IN)
+            .await
+            .unwrap_err();
+        assert_eq!(err, DnsError::AliasLoop("loop.a.example.".to_string()));
+    }
+
+    #[test]
+    fn test_no_nameservers() {
+        assert!(Resolver::new(ResolverConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_order_sequential() {
+        let resolver = new_resolver(addrs(3), Strategy::Sequential);
+        let now = Instant::now();
+        assert_eq!(resolver.order(now), vec![0, 1, 2]);
+        assert_eq!(resolver.order(now), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_order_round_robin() {
+        let resolver = new_resolver(addrs(3), Strategy::RoundRobin);
+        let now = Instant::now();
+        assert_eq!(resolver.order(now), vec![0, 1, 2]);
+        assert_eq!(resolver.order(now), vec![1, 2, 0]);
+        assert_eq!(resolver.order(now), vec![2, 0, 1]);
+        assert_eq!(resolver.order(now), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_order_fastest() {
+        let resolver = new_resolver(addrs(3), Strategy::Fastest);
+        resolver.update(0, |state| state.record_rtt(Duration::from_millis(80)));
+        resolver.update(1, |state| state.record_rtt(Duration::from_millis(20)));
+        // never queried
+        assert_eq!(resolver.order(Instant::now()), vec![2, 1, 0]);
+        resolver.update(2, |state| state.record_rtt(Duration::from_millis(50)));
+        assert_eq!(resolver.order(Instant::now()), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn test_order_cooldown() {
+        let resolver = new_resolver(addrs(3), Strategy::Sequential);
+        let now = Instant::now();
+        let until = now + Duration::from_secs(60);
+        resolver.update(0, |state| state.cooldown_until = Some(until));
+        assert_eq!(resolver.order(now), vec![1, 2]);
+        assert_eq!(resolver.order(until), vec![0, 1, 2]);
+
+        resolver.update(1, |state| state.cooldown_until = Some(until));
+        resolver.update(2, |state| state.cooldown_until = Some(until));
+        assert_eq!(resolver.order(now), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_smoothed_rtt() {
+        let mut state = ServerState::default();
+        state.record_rtt(Duration::from_millis(80));
+        assert_eq!(state.srtt, Some(Duration::from_millis(80)));
+        state.record_rtt(Duration::from_millis(160));
+        assert_eq!(state.srtt, Some(Duration::from_millis(90)));
+    }
+
+    #[tokio::test]
+    async fn test_failover_on_timeout() {
+        let (silent, _) = server(None).await;
+        let (working, _) = server(Some(ResponseCode::NoError)).await;
+        let resolver = new_resolver(vec![silent, working], Strategy::Sequential);
+
+        let response = resolver.query(&query()).await.unwrap();
+        assert_eq!(response.server, working);
+    }
+
+    #[tokio::test]
+    async fn test_cooldown_on_server_failure() {
+        let (failing, failing_count) = server(Some(ResponseCode::ServerFailure)).await;
+        let (refusing, refusing_count) = server(Some(ResponseCode::Refused)).await;
+        let (working, working_count) = server(Some(ResponseCode::NoError)).await;
+        let resolver = new_resolver(vec![failing, refusing, working], Strategy::Sequential);
+
+        for _ in 0..3 {
+            let response = resolver.query(&query()).await.unwrap();
+            assert_eq!(response.server, working);
+            assert_eq!(response.message.response_code(), ResponseCode::NoError);
+        }
+        assert_eq!(failing_count.load(Ordering::SeqCst), 1);
+        assert_eq!(refusing_count.load(Ordering::SeqCst), 1);
+        assert_eq!(working_count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_every_server_failing() {
+        let (failing, _) = server(Some(ResponseCode::ServerFailure)).await;
+        let (refusing, _) = server(Some(ResponseCode::Refused)).await;
+        let resolver = new_resolver(vec![failing, refusing], Strategy::Sequential);
+        let response = resolver.query(&query()).await.unwrap();
+        assert_eq!(response.server, refusing);
+        assert_eq!(response.message.response_code(), ResponseCode::Refused);
+
+        let (silent, _) = server(None).await;
+        let resolver = new_resolver(vec![silent], Strategy::Sequential);
+        assert_eq!(
+            resolver.query(&query()).await.unwrap_err(),
+            DnsError::Timeout(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fastest_avoids_timeouts() {
+        let (silent, silent_count) = server(None).await;
+        let (working, _) = server(Some(ResponseCode::NoError)).await;
+        let resolver = new_resolver(vec![silent, working], Strategy::Fastest);
+
+        for _ in 0..3 {
+            let response = resolver.query(&query()).await.unwrap();
+            assert_eq!(response.server, working);
+        }
+        assert_eq!(silent_count.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,187 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use super::config::{parse_nameserver, ResolverConfig, Strategy};
+use crate::client::{Transport, DNS_PORT};
+use crate::error::DnsError;
+use crate::rr::record::fqdn;
+
+/// Where the system resolver configuration lives.
+pub const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+/// Only the first name servers are used, as in resolv.conf(5).
+const MAX_NAMESERVERS: usize = 3;
+
+/// Caps on the values of `options`, as in resolv.conf(5).
+const MAX_NDOTS: u8 = 15;
+const MAX_TIMEOUT_SECS: u64 = 30;
+const MAX_ATTEMPTS: usize = 5;
+
+/// Read a resolver configuration from the resolv.conf file at `path`,
+/// usually [`RESOLV_CONF_PATH`].
+pub fn read(path: impl AsRef<Path>) -> Result<ResolverConfig, DnsError> {
+    let path = path.as_ref();
+    debug!("Reading {}", path.display());
+    Ok(parse(&fs::read_to_string(path)?))
+}
+
+/// Parse the contents of a resolv.conf file.
+///
+/// Like the C library does, lines that cannot be understood are ignored, and
+/// the name server on the local machine is used when none is listed.
+pub fn parse(contents: &str) -> ResolverConfig {
+    let mut config = ResolverConfig::default();
+    for line in contents.lines() {
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        match keyword {
+            _ if keyword.starts_with(['#', ';']) => {}
+            "nameserver" => match words.next().map(|s| parse_nameserver(s, DNS_PORT)) {
+                Some(Ok(addr)) if config.nameservers.len() < MAX_NAMESERVERS => {
+                    config.nameservers.push(addr)
+                }
+                Some(Ok(addr)) => warn!("Ignoring name server {addr}, over the max of 3"),
+                Some(Err(e)) => warn!("Ignoring name server: {e}"),
+                None => warn!("Ignoring a nameserver line without address"),
+            },
+            // The last of `domain` and `search` wins.
+            "domain" => config.search = words.next().map(fqdn).into_iter().collect(),
+            "search" => config.search = words.map(fqdn).collect(),
+            "options" => words.for_each(|option| parse_option(&mut config, option)),
+            other => debug!("Ignoring unknown keyword {other}"),
+        }
+    }
+    if config.nameservers.is_empty() {
+        config
+            .nameservers
+            .push(SocketAddr::from(([127, 0, 0, 1], DNS_PORT)));
+    }
+    config
+}
+
+fn parse_option(config: &mut ResolverConfig, option: &str) {
+    let (name, value) = match option.split_once(':') {
+        Some((name, value)) => (name, value.parse::<u64>().ok()),
+        None => (option, None),
+    };
+    match (name, value) {
+        ("ndots", Some(ndots)) => config.ndots = ndots.min(MAX_NDOTS.into()) as u8,
+        ("timeout", Some(secs)) => {
+            config.timeout = Duration::from_secs(secs.clamp(1, MAX_TIMEOUT_SECS))
+        }
+        ("attempts", Some(attempts)) => {
+            config.attempts = (attempts as usize).clamp(1, MAX_ATTEMPTS)
+        }
+        ("rotate", None) => config.strategy = Strategy::RoundRobin,
+        ("edns0", None) => config.edns0 = true,
+        ("use-vc", None) => config.transport = Transport::Tcp,
+        _ => debug!("Ignoring option {option}"),
+    }
+}
+
+#[cfg(test)]
+mod tests_resolv_conf {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = parse(
+            "# generated by a container runtime
+nameserver 192.0.2.1
+nameserver 2001:db8::53
+; a comment
+search svc.cluster.local cluster.local
+options ndots:5 timeout:2 attempts:3 rotate edns0 use-vc
+",
+        );
+        assert_eq!(
+            config.nameservers,
+            vec![
+                "192.0.2.1:53".parse::<SocketAddr>().unwrap(),
+                "[2001:db8::53]:53".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.search, vec!["svc.cluster.local.", "cluster.local."]);
+        assert_eq!(config.ndots, 5);
+        assert_eq!(config.timeout, Duration::from_secs(2));
+        assert_eq!(config.attempts, 3);
+        assert_eq!(config.strategy, Strategy::RoundRobin);
+        assert!(config.edns0);
+        assert_eq!(config.transport, Transport::Tcp);
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = parse("");
+        assert_eq!(
+            config.nameservers,
+            vec!["127.0.0.1:53".parse::<SocketAddr>().unwrap()]
+        );
+        assert_eq!(
+            config,
+            ResolverConfig {
+                nameservers: config.nameservers.clone(),
+                ..ResolverConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_last_of_domain_and_search_wins() {
+        assert_eq!(
+            parse("search a.example b.example\ndomain c.example").search,
+            vec!["c.example."]
+        );
+        assert_eq!(
+            parse("domain c.example\nsearch a.example b.example").search,
+            vec!["a.example.", "b.example."]
+        );
+    }
+
+    #[test]
+    fn test_option_caps() {
+        let config = parse("options ndots:40 timeout:0 attempts:9");
+        assert_eq!(config.ndots, 15);
+        assert_eq!(config.timeout, Duration::from_secs(1));
+        assert_eq!(config.attempts, 5);
+    }
+
+    #[test]
+    fn test_ignore_invalid_lines() {
+        let config = parse(
+            "nameserver
+nameserver not-an-address
+nameserver 192.0.2.1
+nameserver 192.0.2.2
+nameserver 192.0.2.3
+nameserver 192.0.2.4
+sortlist 130.155.160.0/255.255.240.0
+options ndots:x debug inet6 timeout
+",
+        );
+        assert_eq!(config.nameservers.len(), 3);
+        assert_eq!(config.nameservers[2], "192.0.2.3:53".parse().unwrap());
+        assert_eq!(config.ndots, 1);
+        assert_eq!(config.timeout, ResolverConfig::default().timeout);
+    }
+
+    #[test]
+    fn test_read() {
+        let path = std::env::temp_dir().join(format!("resolv.conf.{}", std::process::id()));
+        fs::write(&path, "nameserver 192.0.2.1\n").unwrap();
+        let config = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.nameservers, vec!["192.0.2.1:53".parse().unwrap()]);
+
+        assert!(matches!(
+            read("/nonexistent/resolv.conf"),
+            Err(DnsError::Io(std::io::ErrorKind::NotFound, _))
+        ));
+    }
+}
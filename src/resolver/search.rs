@@ -0,0 +1,91 @@
+use crate::rr::record::fqdn;
+
+/// The fully qualified names to query for `name`, in the order the C library
+/// tries them (resolv.conf(5)).
+///
+/// A name ending with a dot is already fully qualified, and is the only
+/// candidate. Otherwise, a name with at least `ndots` dots is tried as is
+/// before being appended each of the `search` domains, and after them when it
+/// has fewer.
+pub fn candidates(name: &str, search: &[String], ndots: u8) -> Vec<String> {
+    if name.ends_with('.') {
+        return vec![name.to_string()];
+    }
+    let absolute = fqdn(name);
+    let searched = search.iter().map(|domain| {
+        let domain = domain.trim_end_matches('.');
+        fqdn(&format!("{name}.{domain}"))
+    });
+    let dots = name.matches('.').count();
+    let mut candidates: Vec<String> = if dots >= usize::from(ndots) {
+        std::iter::once(absolute.clone()).chain(searched).collect()
+    } else {
+        searched.chain(std::iter::once(absolute.clone())).collect()
+    };
+    // A search domain can be the root, making a candidate appear twice.
+    let mut seen = Vec::new();
+    candidates.retain(|candidate| {
+        let first = !seen.contains(candidate);
+        seen.push(candidate.clone());
+        first
+    });
+    candidates
+}
+
+#[cfg(test)]
+mod tests_search {
+    use super::*;
+
+    fn search() -> Vec<String> {
+        vec!["svc.cluster.local.".to_string(), "example.com".to_string()]
+    }
+
+    #[test]
+    fn test_fully_qualified() {
+        assert_eq!(candidates("www.", &search(), 5), vec!["www."]);
+        assert_eq!(candidates(".", &search(), 1), vec!["."]);
+    }
+
+    #[test]
+    fn test_below_ndots() {
+        assert_eq!(
+            candidates("api", &search(), 1),
+            vec!["api.svc.cluster.local.", "api.example.com.", "api."]
+        );
+        assert_eq!(
+            candidates("api.prod", &search(), 5),
+            vec![
+                "api.prod.svc.cluster.local.",
+                "api.prod.example.com.",
+                "api.prod."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_at_least_ndots() {
+        assert_eq!(
+            candidates("www.google", &search(), 1),
+            vec![
+                "www.google.",
+                "www.google.svc.cluster.local.",
+                "www.google.example.com."
+            ]
+        );
+        assert_eq!(candidates("api", &search(), 0)[0], "api.");
+    }
+
+    #[test]
+    fn test_no_search_list() {
+        assert_eq!(candidates("api", &[], 1), vec!["api."]);
+    }
+
+    #[test]
+    fn test_root_search_domain() {
+        let search = vec![".".to_string(), "example.com.".to_string()];
+        assert_eq!(
+            candidates("api", &search, 1),
+            vec!["api.", "api.example.com."]
+        );
+    }
+}
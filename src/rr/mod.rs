@@ -1,2 +1,4 @@
+pub mod rdata;
+pub mod record;
+pub mod record_class;
 pub mod record_type;
-pub mod record_class;
\ No newline at end of file
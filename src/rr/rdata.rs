@@ -0,0 +1,600 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use bitvec::prelude::*;
+use nom::{
+    bytes::complete::take,
+    combinator::{map, rest},
+    multi::{length_data, many0},
+    number::complete::{be_u16, be_u32, be_u8},
+    sequence::tuple,
+    IResult,
+};
+
+use crate::error::DnsError;
+use crate::message::name::{parse_name, NameCompressor};
+use crate::rr::record::{labels_to_name, name_to_labels};
+use crate::rr::record_type::RecordType;
+
+/// The RDATA of a resource record, decoded according to its type (RFC 1035 3.3).
+/// Domain names are kept fully qualified, e.g. `google.com.`.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum RData {
+    /// A 32 bit Internet address.
+    A(Ipv4Addr),
+    /// A host which should be authoritative for the specified class and domain.
+    NS(String),
+    /// A host which has a mail agent for the domain (Obsolete - use MX).
+    MD(String),
+    /// A host which has a mail agent for the domain (Obsolete - use MX).
+    MF(String),
+    /// The canonical or primary name for the owner. The owner name is an alias.
+    CNAME(String),
+    /// Marks the start of a zone of authority.
+    SOA {
+        /// The name server that was the original or primary source of data for this zone.
+        mname: String,
+        /// The mailbox of the person responsible for this zone.
+        rname: String,
+        /// The version number of the original copy of the zone.
+        serial: u32,
+        /// Time interval before the zone should be refreshed.
+        refresh: u32,
+        /// Time interval that should elapse before a failed refresh should be retried.
+        retry: u32,
+        /// Upper limit on the time interval that can elapse before the zone is no longer authoritative.
+        expire: u32,
+        /// The minimum TTL field that should be exported with any RR from this zone.
+        minimum: u32,
+    },
+    /// A host which has the specified mailbox.
+    MB(String),
+    /// A mailbox which is a member of the mail group specified by the domain name.
+    MG(String),
+    /// A mailbox which is the proper rename of the specified mailbox.
+    MR(String),
+    /// Anything at all may be in the RDATA field so long as it is 65535 octets or less.
+    NULL(Vec<u8>),
+    /// A well known service description.
+    WKS {
+        address: Ipv4Addr,
+        /// An 8 bit IP protocol number.
+        protocol: u8,
+        /// One bit per port of the specified protocol.
+        bitmap: Vec<u8>,
+    },
+    /// A pointer to some location in the domain name space.
+    PTR(String),
+    /// Host information.
+    HINFO { cpu: Vec<u8>, os: Vec<u8> },
+    /// Mailbox or mail list information.
+    MINFO {
+        /// A mailbox which is responsible for the mailing list or mailbox.
+        rmailbx: String,
+        /// A mailbox which is to receive error messages related to the mailing list or mailbox.
+        emailbx: String,
+    },
+    /// Mail exchange.
+    MX {
+        /// The preference given to this RR among others at the same owner. Lower values are preferred.
+        preference: u16,
+        /// A host willing to act as a mail exchange for the owner name.
+        exchange: String,
+    },
+    /// One or more character-strings.
+    TXT(Vec<Vec<u8>>),
+    /// A 128 bit IPv6 address (RFC 3596).
+    AAAA(Ipv6Addr),
+    /// The name substituted for the owner as a suffix of the names below it (RFC 6672).
+    DNAME(String),
+    /// The RDATA of a type we do not decode, kept as is (RFC 3597).
+    Unknown {
+        record_type: RecordType,
+        data: Vec<u8>,
+    },
+}
+
+impl RData {
+    /// The record type this data belongs to.
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            RData::A(_) => RecordType::A,
+            RData::NS(_) => RecordType::NS,
+            RData::MD(_) => RecordType::MD,
+            RData::MF(_) => RecordType::MF,
+            RData::CNAME(_) => RecordType::CNAME,
+            RData::SOA { .. } => RecordType::SOA,
+            RData::MB(_) => RecordType::MB,
+            RData::MG(_) => RecordType::MG,
+            RData::MR(_) => RecordType::MR,
+            RData::NULL(_) => RecordType::NULL,
+            RData::WKS { .. } => RecordType::WKS,
+            RData::PTR(_) => RecordType::PTR,
+            RData::HINFO { .. } => RecordType::HINFO,
+            RData::MINFO { .. } => RecordType::MINFO,
+            RData::MX { .. } => RecordType::MX,
+            RData::TXT(_) => RecordType::TXT,
+            RData::AAAA(_) => RecordType::AAAA,
+            RData::DNAME(_) => RecordType::DNAME,
+            RData::Unknown { record_type, .. } => *record_type,
+        }
+    }
+
+    pub fn as_bitvec(&self) -> Result<BitVec<usize, Msb0>, DnsError> {
+        let mut bv = BitVec::<usize, Msb0>::new();
+        self.write(&mut bv, &mut NameCompressor::new(false))?;
+        Ok(bv)
+    }
+
+    /// Append this RDATA to `bv`, which holds the message written so far.
+    pub fn write(
+        &self,
+        bv: &mut BitVec<usize, Msb0>,
+        names: &mut NameCompressor,
+    ) -> Result<(), DnsError> {
+        let mut write_name = |bv: &mut BitVec<usize, Msb0>, name: &str| {
+            let labels: Vec<&str> = name_to_labels(name).collect();
+            names.write_name(bv, &labels)
+        };
+        match self {
+            RData::A(address) => write_bytes(bv, &address.octets()),
+            RData::NS(name)
+            | RData::MD(name)
+            | RData::MF(name)
+            | RData::CNAME(name)
+            | RData::MB(name)
+            | RData::MG(name)
+            | RData::MR(name)
+            | RData::PTR(name) => write_name(bv, name)?,
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                write_name(bv, mname)?;
+                write_name(bv, rname)?;
+                for field in [serial, refresh, retry, expire, minimum] {
+                    bv.extend_from_bitslice(field.view_bits::<Msb0>());
+                }
+            }
+            RData::NULL(data) | RData::Unknown { data, .. } => write_bytes(bv, data),
+            RData::WKS {
+                address,
+                protocol,
+                bitmap,
+            } => {
+                write_bytes(bv, &address.octets());
+                bv.extend_from_bitslice(protocol.view_bits::<Msb0>());
+                write_bytes(bv, bitmap);
+            }
+            RData::HINFO { cpu, os } => {
+                write_character_string(bv, cpu)?;
+                write_character_string(bv, os)?;
+            }
+            RData::MINFO { rmailbx, emailbx } => {
+                write_name(bv, rmailbx)?;
+                write_name(bv, emailbx)?;
+            }
+            RData::MX {
+                preference,
+                exchange,
+            } => {
+                bv.extend_from_bitslice(preference.view_bits::<Msb0>());
+                write_name(bv, exchange)?;
+            }
+            RData::TXT(strings) => {
+                for string in strings {
+                    write_character_string(bv, string)?;
+                }
+            }
+            RData::AAAA(address) => write_bytes(bv, &address.octets()),
+            // The target must not be compressed (RFC 6672 2.5).
+            RData::DNAME(name) => {
+                let labels: Vec<&str> = name_to_labels(name).collect();
+                NameCompressor::new(false).write_name(bv, &labels)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the RDATA of a `record_type` record. `i` must hold exactly RDLENGTH
+    /// bytes, and `msg` is the whole message it was taken from.
+    pub fn deserialize<'a>(
+        msg: &'a [u8],
+        i: &'a [u8],
+        record_type: RecordType,
+    ) -> IResult<&'a [u8], Self, DnsError> {
+        let name = move |i: &'a [u8]| map(|i| parse_name(msg, i), |l| labels_to_name(&l))(i);
+        let parser = move |i: &'a [u8]| match record_type {
+            RecordType::A => map(take(4usize), |b: &[u8]| {
+                RData::A(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+            })(i),
+            RecordType::NS => map(name, RData::NS)(i),
+            RecordType::MD => map(name, RData::MD)(i),
+            RecordType::MF => map(name, RData::MF)(i),
+            RecordType::CNAME => map(name, RData::CNAME)(i),
+            RecordType::SOA => map(
+                tuple((name, name, be_u32, be_u32, be_u32, be_u32, be_u32)),
+                |(mname, rname, serial, refresh, retry, expire, minimum)| RData::SOA {
+                    mname,
+                    rname,
+                    serial,
+                    refresh,
+                    retry,
+                    expire,
+                    minimum,
+                },
+            )(i),
+            RecordType::MB => map(name, RData::MB)(i),
+            RecordType::MG => map(name, RData::MG)(i),
+            RecordType::MR => map(name, RData::MR)(i),
+            RecordType::NULL => map(rest, |b: &[u8]| RData::NULL(b.to_vec()))(i),
+            RecordType::WKS => map(
+                tuple((take(4usize), be_u8, rest)),
+                |(a, protocol, bitmap): (&[u8], u8, &[u8])| RData::WKS {
+                    address: Ipv4Addr::new(a[0], a[1], a[2], a[3]),
+                    protocol,
+                    bitmap: bitmap.to_vec(),
+                },
+            )(i),
+            RecordType::PTR => map(name, RData::PTR)(i),
+            RecordType::HINFO => map(tuple((character_string, character_string)), |(cpu, os)| {
+                RData::HINFO { cpu, os }
+            })(i),
+            RecordType::MINFO => map(tuple((name, name)), |(rmailbx, emailbx)| RData::MINFO {
+                rmailbx,
+                emailbx,
+            })(i),
+            RecordType::MX => map(tuple((be_u16, name)), |(preference, exchange)| RData::MX {
+                preference,
+                exchange,
+            })(i),
+            RecordType::TXT => map(many0(character_string), RData::TXT)(i),
+            RecordType::AAAA => map(take(16usize), |b: &[u8]| {
+                let octets: [u8; 16] = b.try_into().expect("took 16 bytes");
+                RData::AAAA(Ipv6Addr::from(octets))
+            })(i),
+            RecordType::DNAME => map(name, RData::DNAME)(i),
+            RecordType::Unknown(_) | RecordType::IXFR | RecordType::AXFR | RecordType::ANY => {
+                map(rest, |b: &[u8]| RData::Unknown {
+                    record_type,
+                    data: b.to_vec(),
+                })(i)
+            }
+        };
+        let (i, rdata) = parser(i)?;
+        if !i.is_empty() {
+            return Err(nom::Err::Failure(DnsError::TrailingGarbage(i.len())));
+        }
+        Ok((i, rdata))
+    }
+
+    /// Parse RDATA of `record_type` in the generic `\# length hex` format
+    /// (RFC 3597 5), e.g. `\# 4 c0000201`. The hexadecimal may be split in
+    /// several words. The data of a type we know of is decoded, and must not
+    /// hold compressed names. Types only valid in questions have no data.
+    pub fn parse_generic(record_type: RecordType, s: &str) -> Result<Self, String> {
+        if record_type.is_meta() {
+            return Err(DnsError::MetaType(record_type).to_string());
+        }
+        let mut words = s.split_whitespace();
+        if words.next() != Some("\\#") {
+            return Err(format!("{s} does not start with \\#"));
+        }
+        let len: usize = words
+            .next()
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| format!("{s} has no valid length"))?;
+        let data = hex::decode(words.collect::<String>()).map_err(|e| format!("{s}: {e}"))?;
+        if data.len() != len {
+            return Err(format!("{s} holds {} bytes, not {len}", data.len()));
+        }
+        match record_type {
+            RecordType::Unknown(_) => Ok(RData::Unknown { record_type, data }),
+            // Without a message to point into, every compression pointer is rejected.
+            _ => RData::deserialize(&[], &data, record_type)
+                .map(|(_, rdata)| rdata)
+                .map_err(|e| format!("{s} is not valid {record_type} data: {}", DnsError::from(e))),
+        }
+    }
+}
+
+/// The presentation format of RFC 1035 5.1. The data of unknown types, and
+/// of NULL which has none, is in the generic format of RFC 3597 5.
+impl fmt::Display for RData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RData::A(address) => write!(f, "{address}"),
+            RData::NS(name)
+            | RData::MD(name)
+            | RData::MF(name)
+            | RData::CNAME(name)
+            | RData::MB(name)
+            | RData::MG(name)
+            | RData::MR(name)
+            | RData::PTR(name)
+            | RData::DNAME(name) => write!(f, "{name}"),
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{mname} {rname} {serial} {refresh} {retry} {expire} {minimum}"
+            ),
+            RData::NULL(data) | RData::Unknown { data, .. } => {
+                write!(f, "\\# {}", data.len())?;
+                if !data.is_empty() {
+                    write!(f, " {}", hex::encode(data))?;
+                }
+                Ok(())
+            }
+            RData::WKS {
+                address,
+                protocol,
+                bitmap,
+            } => {
+                write!(f, "{address} {protocol}")?;
+                for (ix, byte) in bitmap.iter().enumerate() {
+                    for bit in (0..8).filter(|bit| byte & (0x80 >> bit) != 0) {
+                        write!(f, " {}", ix * 8 + bit)?;
+                    }
+                }
+                Ok(())
+            }
+            RData::HINFO { cpu, os } => {
+                write_quoted(f, cpu)?;
+                write!(f, " ")?;
+                write_quoted(f, os)
+            }
+            RData::MINFO { rmailbx, emailbx } => write!(f, "{rmailbx} {emailbx}"),
+            RData::MX {
+                preference,
+                exchange,
+            } => write!(f, "{preference} {exchange}"),
+            RData::TXT(strings) => {
+                for (ix, string) in strings.iter().enumerate() {
+                    if ix > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_quoted(f, string)?;
+                }
+                Ok(())
+            }
+            RData::AAAA(address) => write!(f, "{address}"),
+        }
+    }
+}
+
+/// A character-string between quotes, with quotes, backslashes and
+/// unprintable octets escaped (RFC 1035 5.1).
+fn write_quoted(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    write!(f, "\"")?;
+    for byte in data {
+        match byte {
+            b'"' | b'\\' => write!(f, "\\{}", *byte as char)?,
+            0x20..=0x7e => write!(f, "{}", *byte as char)?,
+            _ => write!(f, "\\{byte:03}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// A single length octet followed by that number of octets (RFC 1035 3.3).
+fn character_string(i: &[u8]) -> IResult<&[u8], Vec<u8>, DnsError> {
+    map(length_data(be_u8), <[u8]>::to_vec)(i)
+}
+
+fn write_character_string(bv: &mut BitVec<usize, Msb0>, data: &[u8]) -> Result<(), DnsError> {
+    let len = u8::try_from(data.len()).map_err(|_| DnsError::CharacterStringTooLong(data.len()))?;
+    bv.extend_from_bitslice(len.view_bits::<Msb0>());
+    write_bytes(bv, data);
+    Ok(())
+}
+
+fn write_bytes(bv: &mut BitVec<usize, Msb0>, data: &[u8]) {
+    data.iter()
+        .for_each(|byte| bv.extend_from_bitslice(byte.view_bits::<Msb0>()));
+}
+
+#[cfg(test)]
+mod tests_rdata {
+    use super::*;
+    use std::io::Read;
+
+    fn round_trip(rdata: RData) {
+        let mut bytes = Vec::new();
+        rdata.as_bitvec().unwrap().read_to_end(&mut bytes).unwrap();
+        let (rest, parsed) = RData::deserialize(&bytes, &bytes, rdata.record_type()).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed, rdata);
+    }
+
+    #[test]
+    fn test_round_trip_all_types() {
+        let all = vec![
+            RData::A(Ipv4Addr::new(142, 250, 179, 78)),
+            RData::NS("ns1.google.com.".to_string()),
+            RData::MD("md.example.".to_string()),
+            RData::MF("mf.example.".to_string()),
+            RData::CNAME("www.google.com.".to_string()),
+            RData::SOA {
+                mname: "ns1.google.com.".to_string(),
+                rname: "dns-admin.google.com.".to_string(),
+                serial: 2024010100,
+                refresh: 900,
+                retry: 900,
+                expire: 1800,
+                minimum: 60,
+            },
+            RData::MB("mb.example.".to_string()),
+            RData::MG("mg.example.".to_string()),
+            RData::MR("mr.example.".to_string()),
+            RData::NULL(vec![0, 1, 2, 255]),
+            RData::WKS {
+                address: Ipv4Addr::new(10, 0, 0, 1),
+                protocol: 6,
+                bitmap: vec![0b0000_0000, 0b0100_0000],
+            },
+            RData::PTR("localhost.".to_string()),
+            RData::HINFO {
+                cpu: b"x86_64".to_vec(),
+                os: b"Linux".to_vec(),
+            },
+            RData::MINFO {
+                rmailbx: "admin.example.".to_string(),
+                emailbx: "errors.example.".to_string(),
+            },
+            RData::MX {
+                preference: 10,
+                exchange: "smtp.google.com.".to_string(),
+            },
+            RData::TXT(vec![b"v=spf1 -all".to_vec(), Vec::new()]),
+            RData::AAAA("2a00:1450:4007:80e::200e".parse().unwrap()),
+            RData::DNAME("example.net.".to_string()),
+        ];
+        for rdata in all {
+            round_trip(rdata);
+        }
+    }
+
+    #[test]
+    fn test_deserialize_mx_compressed() {
+        let msg: &[u8] = &[
+            6, b'g', b'o', b'o', b'g', b'l', b'e', 3, b'c', b'o', b'm', 0, // offset 0
+            0, 10, 4, b's', b'm', b't', b'p', 0xc0, 0, // rdata
+        ];
+        let (_, rdata) = RData::deserialize(msg, &msg[12..], RecordType::MX).unwrap();
+        assert_eq!(
+            rdata,
+            RData::MX {
+                preference: 10,
+                exchange: "smtp.google.com.".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_write_dname_uncompressed() {
+        let mut names = NameCompressor::default();
+        let mut bv = BitVec::<usize, Msb0>::new();
+        names.write_name(&mut bv, &["example", "net"]).unwrap();
+        let start = bv.len() / 8;
+        RData::DNAME("example.net.".to_string())
+            .write(&mut bv, &mut names)
+            .unwrap();
+        let mut bytes = Vec::new();
+        bv.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes[start..], bytes[..start]);
+    }
+
+    #[test]
+    fn test_deserialize_wrong_length() {
+        let bytes: &[u8] = &[127, 0, 0];
+        let err = RData::deserialize(bytes, bytes, RecordType::A).unwrap_err();
+        assert_eq!(DnsError::from(err), DnsError::Truncated);
+        let bytes: &[u8] = &[127, 0, 0, 1, 0];
+        let err = RData::deserialize(bytes, bytes, RecordType::A).unwrap_err();
+        assert_eq!(DnsError::from(err), DnsError::TrailingGarbage(1));
+    }
+
+    #[test]
+    fn test_character_string_too_long() {
+        let rdata = RData::TXT(vec![vec![b'a'; 256]]);
+        assert_eq!(
+            rdata.as_bitvec().unwrap_err(),
+            DnsError::CharacterStringTooLong(256)
+        );
+    }
+
+    #[test]
+    fn test_unknown_type() {
+        let bytes: &[u8] = &[0xde, 0xad, 0xbe, 0xef];
+        let record_type = RecordType::Unknown(65280);
+        let (_, rdata) = RData::deserialize(bytes, bytes, record_type).unwrap();
+        assert_eq!(
+            rdata,
+            RData::Unknown {
+                record_type,
+                data: bytes.to_vec()
+            }
+        );
+        assert_eq!(rdata.record_type(), record_type);
+        round_trip(rdata);
+    }
+
+    #[test]
+    fn test_presentation() {
+        let rdata = RData::Unknown {
+            record_type: RecordType::Unknown(731),
+            data: vec![0x0a, 0, 0, 1],
+        };
+        assert_eq!(rdata.to_string(), "\\# 4 0a000001");
+        assert_eq!(RData::NULL(Vec::new()).to_string(), "\\# 0");
+        assert_eq!(
+            RData::MX {
+                preference: 10,
+                exchange: "smtp.google.com.".to_string()
+            }
+            .to_string(),
+            "10 smtp.google.com."
+        );
+        assert_eq!(
+            RData::TXT(vec![b"say \"hi\"".to_vec(), vec![b'\\', 7]]).to_string(),
+            "\"say \\\"hi\\\"\" \"\\\\\\007\""
+        );
+        assert_eq!(
+            RData::WKS {
+                address: Ipv4Addr::new(10, 0, 0, 1),
+                protocol: 6,
+                bitmap: vec![0, 0b0100_0000, 0, 0b0000_0001],
+            }
+            .to_string(),
+            "10.0.0.1 6 9 31"
+        );
+    }
+
+    #[test]
+    fn test_parse_generic() {
+        let record_type = RecordType::Unknown(731);
+        let rdata = RData::Unknown {
+            record_type,
+            data: vec![0x0a, 0, 0, 1],
+        };
+        assert_eq!(
+            RData::parse_generic(record_type, &rdata.to_string()),
+            Ok(rdata)
+        );
+        // the data of known types is decoded
+        assert_eq!(
+            RData::parse_generic(RecordType::A, "\\# 4 0A00 0001"),
+            Ok(RData::A(Ipv4Addr::new(10, 0, 0, 1)))
+        );
+        assert_eq!(
+            RData::parse_generic(record_type, "\\# 0"),
+            Ok(RData::Unknown {
+                record_type,
+                data: Vec::new()
+            })
+        );
+        assert!(RData::parse_generic(record_type, "0a000001").is_err());
+        assert!(RData::parse_generic(record_type, "\\# 3 0a000001").is_err());
+        assert!(RData::parse_generic(record_type, "\\# 1 0g").is_err());
+        assert!(RData::parse_generic(RecordType::A, "\\# 3 0a0000").is_err());
+        // names are not compressed, as there is no message to point into
+        assert!(RData::parse_generic(RecordType::MX, "\\# 3 000a00").is_ok());
+        assert!(RData::parse_generic(RecordType::MX, "\\# 4 0000c000").is_err());
+        for record_type in [RecordType::ANY, RecordType::AXFR, RecordType::IXFR] {
+            assert!(RData::parse_generic(record_type, "\\# 0").is_err());
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use crate::error::DnsError;
+use crate::message::name::{parse_name, NameCompressor};
+use crate::rr::{rdata::RData, record_class::Class, record_type::RecordType};
+use bitvec::prelude::*;
+
+use log::debug;
+use nom::{
+    combinator::map,
+    multi::length_data,
+    number::complete::{be_u16, be_u32},
+    IResult,
+};
+
+/// A resource record, as found in the answer, authority and additional
+/// sections of a message (RFC 1035 4.1.3).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The domain name to which this resource record pertains.
+    pub name: String,
+    /// The meaning of the data in the RDATA field.
+    pub record_type: RecordType,
+    /// The class of the data in the RDATA field.
+    pub class: Class,
+    /// The time interval (in seconds) that the resource record may be cached before it should be discarded.
+    pub ttl: u32,
+    /// The resource data, whose format varies according to the record type and class.
+    pub rdata: RData,
+}
+
+impl Record {
+    pub fn as_bitvec(&self) -> Result<BitVec<usize, Msb0>, DnsError> {
+        let mut bv = BitVec::<usize, Msb0>::new();
+        self.write(&mut bv, &mut NameCompressor::new(false))?;
+        Ok(bv)
+    }
+
+    /// Append this record to `bv`, which holds the message written so far.
+    pub fn write(
+        &self,
+        bv: &mut BitVec<usize, Msb0>,
+        names: &mut NameCompressor,
+    ) -> Result<(), DnsError> {
+        self.record_type.check()?;
+        self.class.check()?;
+        check_type(self.record_type, self.class)?;
+        let labels: Vec<&str> = name_to_labels(&self.name).collect();
+        names.write_name(bv, &labels)?;
+
+        bv.extend_from_bitslice(self.record_type.as_bitvec().as_bitslice());
+        bv.extend_from_bitslice(self.class.as_bitvec().as_bitslice());
+        bv.extend_from_bitslice(self.ttl.view_bits::<Msb0>());
+
+        // RDLENGTH is only known once RDATA is written, since names in it may be compressed.
+        let rdlength_at = bv.len();
+        bv.extend_from_bitslice(0u16.view_bits::<Msb0>());
+        self.rdata.write(bv, names)?;
+        let rdlength = (bv.len() - rdlength_at - 16) / 8;
+        let rdlength = u16::try_from(rdlength).map_err(|_| DnsError::RDataTooLong(rdlength))?;
+        bv[rdlength_at..rdlength_at + 16].store_be(rdlength);
+
+        Ok(())
+    }
+
+    /// Parse a record from `i`, where `msg` is the whole message it was taken from.
+    pub fn deserialize<'a>(msg: &'a [u8], i: &'a [u8]) -> IResult<&'a [u8], Self, DnsError> {
+        let (i, labels) = parse_name(msg, i)?;
+        let (i, record_type) = map(be_u16, RecordType::from)(i)?;
+        let (i, class) = map(be_u16, Class::from)(i)?;
+        check_type(record_type, class).map_err(nom::Err::Failure)?;
+        let (i, ttl) = be_u32(i)?;
+        let (i, rdata) = length_data(be_u16)(i)?;
+        let rdata = if class.is_meta() && rdata.is_empty() {
+            // what a dynamic update asks about or deletes, rather than data
+            RData::Unknown {
+                record_type,
+                data: Vec::new(),
+            }
+        } else {
+            RData::deserialize(msg, rdata, record_type)?.1
+        };
+
+        let record = Self {
+            name: labels_to_name(&labels),
+            record_type,
+            class,
+            ttl,
+            rdata,
+        };
+        debug!("Found record {:?}", record);
+        Ok((i, record))
+    }
+}
+
+/// Fail when a record cannot have `record_type`. Meta types are only valid in
+/// questions, except ANY with class ANY in a dynamic update, which requires a
+/// name to be in use or deletes all its RRsets (RFC 2136 2.4.4, 2.5.3).
+fn check_type(record_type: RecordType, class: Class) -> Result<(), DnsError> {
+    if record_type.is_meta() && (record_type, class) != (RecordType::ANY, Class::ANY) {
+        return Err(DnsError::MetaType(record_type));
+    }
+    Ok(())
+}
+
+/// Join wire labels (including the terminating empty label) into a fully
+/// qualified name, e.g. `["google", "com", ""]` gives `google.com.`.
+pub(crate) fn labels_to_name(labels: &[&str]) -> String {
+    let name = labels.join(".");
+    if name.is_empty() {
+        ".".to_string()
+    } else {
+        name
+    }
+}
+
+/// Make `name` fully qualified by appending the root label if missing, e.g.
+/// `google.com` gives `google.com.`.
+pub(crate) fn fqdn(name: &str) -> String {
+    if name.ends_with('.') {
+        name.to_string()
+    } else {
+        format!("{name}.")
+    }
+}
+
+/// Split a name into its non-empty labels, e.g. `google.com.` gives `["google", "com"]`.
+pub(crate) fn name_to_labels(name: &str) -> impl Iterator<Item = &str> {
+    name.split('.').filter(|label| !label.is_empty())
+}
+
+/// Whether `name` is `zone` or below it, ignoring case, e.g. `www.google.com.`
+/// is below `com.` and `.`.
+pub(crate) fn is_subdomain(name: &str, zone: &str) -> bool {
+    let name: Vec<&str> = name_to_labels(name).collect();
+    let zone: Vec<&str> = name_to_labels(zone).collect();
+    name.len() >= zone.len()
+        && name[name.len() - zone.len()..]
+            .iter()
+            .zip(&zone)
+            .all(|(label, zone)| label.eq_ignore_ascii_case(zone))
+}
+
+#[cfg(test)]
+mod tests_record {
+    use super::*;
+    use std::io::Read;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_deserialize() {
+        let buffer: &[u8] = &[
+            6, b'g', b'o', b'o', b'g', b'l', b'e', 3, b'c', b'o', b'm', 0, // name
+            0, 1, // type A
+            0, 1, // class IN
+            0, 0, 0x0e, 0x10, // ttl 3600
+            0, 4, // rdlength
+            142, 250, 179, 78,   // rdata
+            0xff, // next section
+        ];
+        let (rest, record) = Record::deserialize(buffer, buffer).unwrap();
+        assert_eq!(rest, &[0xff]);
+        assert_eq!(record.name, "google.com.");
+        assert_eq!(record.record_type, RecordType::A);
+        assert_eq!(record.class, Class::IN);
+        assert_eq!(record.ttl, 3600);
+        assert_eq!(record.rdata, RData::A(Ipv4Addr::new(142, 250, 179, 78)));
+    }
+
+    #[test]
+    fn test_deserialize_compressed_name() {
+        let msg: &[u8] = &[
+            6, b'g', b'o', b'o', b'g', b'l', b'e', 3, b'c', b'o', b'm', 0, // offset 0
+            0xc0, 0, // name, pointer to offset 0
+            0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 127, 0, 0, 1,
+        ];
+        let (rest, record) = Record::deserialize(msg, &msg[12..]).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(record.name, "google.com.");
+        assert_eq!(record.rdata, RData::A(Ipv4Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn test_deserialize_truncated_rdata() {
+        let buffer: &[u8] = &[0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 4, 127, 0];
+        assert!(Record::deserialize(buffer, buffer).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let record = Record {
+            name: "google.com.".to_string(),
+            record_type: RecordType::A,
+            class: Class::IN,
+            ttl: 300,
+            rdata: RData::A(Ipv4Addr::new(1, 2, 3, 4)),
+        };
+        let mut bytes = Vec::new();
+        record.as_bitvec().unwrap().read_to_end(&mut bytes).unwrap();
+        let (_, parsed) = Record::deserialize(&bytes, &bytes).unwrap();
+        assert_eq!(parsed, record);
+    }
+
+    #[test]
+    fn test_write_compressed_rdata() {
+        let record = Record {
+            name: "google.com.".to_string(),
+            record_type: RecordType::MX,
+            class: Class::IN,
+            ttl: 300,
+            rdata: RData::MX {
+                preference: 10,
+                exchange: "smtp.google.com.".to_string(),
+            },
+        };
+        let mut bv = BitVec::<usize, Msb0>::new();
+        record
+            .write(&mut bv, &mut NameCompressor::default())
+            .unwrap();
+        let mut bytes = Vec::new();
+        bv.read_to_end(&mut bytes).unwrap();
+        // preference, `smtp` and a pointer to the owner name
+        assert_eq!(bytes[20..22], [0, 2 + 5 + 2]);
+        assert_eq!(bytes[bytes.len() - 2..], [0xc0, 0]);
+
+        let (rest, parsed) = Record::deserialize(&bytes, &bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed, record);
+    }
+
+    #[test]
+    fn test_meta_type() {
+        let mut buffer = vec![0, 0, 252, 0, 1, 0, 0, 0, 0, 0, 0];
+        let err = Record::deserialize(&buffer, &buffer).unwrap_err();
+        assert_eq!(DnsError::from(err), DnsError::MetaType(RecordType::AXFR));
+
+        // deleting all the RRsets of a name in a dynamic update
+        buffer[2] = 255;
+        buffer[4] = 255;
+        let (_, record) = Record::deserialize(&buffer, &buffer).unwrap();
+        assert_eq!(record.record_type, RecordType::ANY);
+        assert_eq!(record.class, Class::ANY);
+        assert_eq!(record.as_bitvec().unwrap().len(), buffer.len() * 8);
+
+        // an RRset that must not exist, with an empty RDATA of its own type
+        let buffer = [0, 0, 1, 0, 254, 0, 0, 0, 0, 0, 0];
+        let (_, record) = Record::deserialize(&buffer, &buffer).unwrap();
+        assert_eq!(record.record_type, RecordType::A);
+        assert_eq!(record.class, Class::NONE);
+
+        let record = Record {
+            name: "google.com.".to_string(),
+            record_type: RecordType::ANY,
+            class: Class::IN,
+            ttl: 0,
+            rdata: RData::NULL(Vec::new()),
+        };
+        assert_eq!(
+            record.as_bitvec().unwrap_err(),
+            DnsError::MetaType(RecordType::ANY)
+        );
+    }
+
+    #[test]
+    fn test_aliased_unknown() {
+        let record = Record {
+            name: "google.com.".to_string(),
+            record_type: RecordType::Unknown(28),
+            class: Class::IN,
+            ttl: 0,
+            rdata: RData::Unknown {
+                record_type: RecordType::Unknown(28),
+                data: vec![0; 16],
+            },
+        };
+        assert_eq!(record.as_bitvec().unwrap_err(), DnsError::BadRecordType(28));
+    }
+
+    #[test]
+    fn test_root_name() {
+        assert_eq!(labels_to_name(&[""]), ".");
+        assert_eq!(name_to_labels(".").count(), 0);
+    }
+
+    #[test]
+    fn test_is_subdomain() {
+        assert!(is_subdomain("www.Google.com.", "google.COM."));
+        assert!(is_subdomain("google.com.", "google.com."));
+        assert!(is_subdomain("google.com.", "."));
+        assert!(!is_subdomain("google.com.", "www.google.com."));
+        assert!(!is_subdomain("www.oogle.com.", "google.com."));
+    }
+}
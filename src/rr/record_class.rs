@@ -1,59 +1,104 @@
-use bitvec::{order::Msb0, slice::BitSlice, vec::BitVec, view::BitView};
+use std::{fmt, str::FromStr};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use bitvec::prelude::*;
+
+use super::record_type::parse_number;
+use crate::error::DnsError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Class {
-    IN = 1, //1 the Internet
-    CS,     //2 the CSNET class (Obsolete - used only for examples in some obsolete RFCs)
-    CH,     //3 the CHAOS class
-    HS,     //4 Hesiod [Dyer 87]
+    IN, //1 the Internet
+    CS, //2 the CSNET class (Obsolete - used only for examples in some obsolete RFCs)
+    CH, //3 the CHAOS class
+    HS, //4 Hesiod [Dyer 87]
+    /// 254 no class, in the records of a dynamic update (RFC 2136 2.4, 2.5)
+    NONE,
+    /// 255 any class
+    ANY,
+    /// Any other class (RFC 3597).
+    Unknown(u16),
 }
 
-impl TryFrom<u16> for Class {
-    type Error = anyhow::Error;
-
-    fn try_from(value: u16) -> Result<Self, Self::Error> {
-        let record_type = match value {
-            1 => Self::IN,
-            2 => Self::CS,
-            3 => Self::CH,
-            4 => Self::HS,
-            _ => anyhow::bail!("Invalid record type number {value:b}"),
-        };
-        Ok(record_type)
+/// Classes are written by their mnemonic, or as `CLASS` followed by their
+/// number when they have none (RFC 3597 5).
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(value) => write!(f, "CLASS{value}"),
+            known => write!(f, "{known:?}"),
+        }
     }
 }
 
-impl TryFrom<u8> for Class {
-    type Error = anyhow::Error;
+impl FromStr for Class {
+    type Err = String;
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        return Self::try_from(value as u16);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let class = match s.to_uppercase().as_str() {
+            "IN" => Self::IN,
+            "CS" => Self::CS,
+            "CH" => Self::CH,
+            "HS" => Self::HS,
+            "NONE" => Self::NONE,
+            "ANY" => Self::ANY,
+            other => match other.strip_prefix("CLASS").and_then(parse_number) {
+                Some(value) => Self::from(value),
+                None => return Err(format!("{other} is not a supported DNS class")),
+            },
+        };
+        Ok(class)
     }
 }
 
-
-impl<'a> From<Class> for &'a u16 {
-    fn from(val: Class) -> Self {
-        let type_num = match val {
-            Class::IN => &1,
-            Class::CS => &2,
-            Class::CH => &3,
-            Class::HS => &4,
-        };
-        type_num
+impl From<u16> for Class {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => Self::IN,
+            2 => Self::CS,
+            3 => Self::CH,
+            4 => Self::HS,
+            254 => Self::NONE,
+            255 => Self::ANY,
+            other => Self::Unknown(other),
+        }
     }
 }
+
 impl From<Class> for u16 {
     fn from(val: Class) -> Self {
-        let type_num: &u16 = val.into();
-        *type_num
+        match val {
+            Class::IN => 1,
+            Class::CS => 2,
+            Class::CH => 3,
+            Class::HS => 4,
+            Class::NONE => 254,
+            Class::ANY => 255,
+            Class::Unknown(value) => value,
+        }
     }
 }
 
-impl<'a> Class {
-    pub fn as_bitslice(self) -> &'a BitSlice<u16, Msb0> {
-        let type_num: &'a u16 = self.into();
-        type_num.view_bits::<Msb0>()
+impl Class {
+    /// Whether this is a QCLASS, which records only have in dynamic updates
+    /// (RFC 2136 1.1).
+    pub fn is_meta(self) -> bool {
+        matches!(self, Self::NONE | Self::ANY)
+    }
+
+    /// Fail when this is an unknown class standing for a known one, such as
+    /// `Unknown(1)` for IN, which would not compare equal to what is parsed.
+    pub fn check(self) -> Result<(), DnsError> {
+        let value = u16::from(self);
+        if Self::from(value) != self {
+            return Err(DnsError::BadClass(value));
+        }
+        Ok(())
+    }
+
+    pub fn as_bitvec(self) -> BitVec<usize, Msb0> {
+        let mut bv = BitVec::<usize, Msb0>::new();
+        bv.extend_from_bitslice(u16::from(self).view_bits::<Msb0>());
+        bv
     }
 }
 
@@ -63,10 +108,17 @@ mod tests_class {
 
     #[test]
     fn test_from_u16() {
-        let record_type: Class = (1 as u16).try_into().unwrap();
+        let record_type: Class = 1u16.into();
         assert_eq!(record_type, Class::IN);
     }
 
+    #[test]
+    fn test_from_str() {
+        assert_eq!("in".parse::<Class>().unwrap(), Class::IN);
+        assert_eq!("CH".parse::<Class>().unwrap(), Class::CH);
+        assert!("XX".parse::<Class>().is_err());
+    }
+
     #[test]
     fn test_into_u16() {
         let bitslice: u16 = Class::IN.into();
@@ -75,14 +127,54 @@ mod tests_class {
 
     #[test]
     fn test_all_convert() {
-        for i in 1..4 {
-            let record_type: Class = i.try_into().unwrap();
+        for i in 0..=u16::MAX {
+            let record_type: Class = i.into();
             let n_record_type: u16 = record_type.into();
             assert_eq!(i, n_record_type);
 
-            let bitslice: &BitSlice<u16, Msb0> = record_type.clone().as_bitslice();
-            assert_eq!(bitslice, (i as u16).view_bits::<Msb0>());
-            assert_eq!(bitslice.len(), 16); // two octets
+            let bv = record_type.as_bitvec();
+            assert_eq!(bv, i.view_bits::<Msb0>());
+            assert_eq!(bv.len(), 16); // two octets
         }
+        assert_eq!(Class::from(7), Class::Unknown(7));
+    }
+
+    #[test]
+    fn test_presentation() {
+        assert_eq!(Class::CH.to_string(), "CH");
+        assert_eq!(Class::Unknown(7).to_string(), "CLASS7");
+        assert_eq!("class7".parse::<Class>().unwrap(), Class::Unknown(7));
+        assert_eq!("CLASS1".parse::<Class>().unwrap(), Class::IN);
+        assert!("CLASSX".parse::<Class>().is_err());
+        assert!("CLASS+1".parse::<Class>().is_err());
+        assert!("CLASS".parse::<Class>().is_err());
+        assert_eq!(
+            "XX".parse::<Class>(),
+            Err("XX is not a supported DNS class".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(Class::CH.check().is_ok());
+        assert!(Class::Unknown(7).check().is_ok());
+        for value in [1, 254] {
+            assert_eq!(
+                Class::Unknown(value).check(),
+                Err(DnsError::BadClass(value))
+            );
+        }
+    }
+
+    #[test]
+    fn test_meta() {
+        assert_eq!("none".parse::<Class>().unwrap(), Class::NONE);
+        assert_eq!("ANY".parse::<Class>().unwrap(), Class::ANY);
+        assert_eq!("CLASS255".parse::<Class>().unwrap(), Class::ANY);
+        // only a type in zone files
+        assert!("*".parse::<Class>().is_err());
+        assert_eq!(Class::NONE.to_string(), "NONE");
+        assert!(Class::ANY.is_meta());
+        assert!(!Class::IN.is_meta());
     }
 }
@@ -1,26 +1,48 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
-use bitvec::{order::Msb0, slice::BitSlice, view::BitView};
-#[derive(Debug, Clone, Copy, PartialEq)]
+use bitvec::prelude::*;
+
+use crate::error::DnsError;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RecordType {
-    A = 1,     // 1 a host address
-    NS,        // 2 an authoritative name server
-    MD,        // 3 a mail destination (Obsolete - use MX)
-    MF,        // 4 a mail forwarder (Obsolete - use MX)
-    CNAME,     // 5 the canonical name for an alias
-    SOA,       // 6 marks the start of a zone of authority
-    MB,        // 7 a mailbox domain name (EXPERIMENTAL)
-    MG,        // 8 a mail group member (EXPERIMENTAL)
-    MR,        // 9 a mail rename domain name (EXPERIMENTAL)
-    NULL,      //  10 a null RR (EXPERIMENTAL)
-    WKS,       // 11 a well known service description
-    PTR,       // 12 a domain name pointer
-    HINFO,     // 13 host information
-    MINFO,     // 14 mailbox or mail list information
-    MX,        // 15 mail exchange
-    TXT,       // 16 text strings
-    AAAA = 28, // 28
+    A,     // 1 a host address
+    NS,    // 2 an authoritative name server
+    MD,    // 3 a mail destination (Obsolete - use MX)
+    MF,    // 4 a mail forwarder (Obsolete - use MX)
+    CNAME, // 5 the canonical name for an alias
+    SOA,   // 6 marks the start of a zone of authority
+    MB,    // 7 a mailbox domain name (EXPERIMENTAL)
+    MG,    // 8 a mail group member (EXPERIMENTAL)
+    MR,    // 9 a mail rename domain name (EXPERIMENTAL)
+    NULL,  //  10 a null RR (EXPERIMENTAL)
+    WKS,   // 11 a well known service description
+    PTR,   // 12 a domain name pointer
+    HINFO, // 13 host information
+    MINFO, // 14 mailbox or mail list information
+    MX,    // 15 mail exchange
+    TXT,   // 16 text strings
+    AAAA,  // 28
+    DNAME, // 39 delegation of a subtree of the name space (RFC 6672)
+    IXFR,  // 251 an incremental transfer of a zone (RFC 1995)
+    AXFR,  // 252 a transfer of an entire zone
+    ANY,   // 255 all records
+    /// Any other type, whose RDATA is kept opaque (RFC 3597).
+    Unknown(u16),
+}
+
+/// Types are written by their mnemonic, or as `TYPE` followed by their
+/// number when they have none (RFC 3597 5).
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(value) => write!(f, "TYPE{value}"),
+            known => write!(f, "{known:?}"),
+        }
+    }
 }
+
 impl FromStr for RecordType {
     type Err = String;
 
@@ -43,19 +65,31 @@ impl FromStr for RecordType {
             "MX" => Self::MX,
             "TXT" => Self::TXT,
             "AAAA" => Self::AAAA,
-            other => return Err(format!("{other} is not a supported as DNS record type")),
+            "DNAME" => Self::DNAME,
+            "IXFR" => Self::IXFR,
+            "AXFR" => Self::AXFR,
+            "ANY" | "*" => Self::ANY,
+            other => match other.strip_prefix("TYPE").and_then(parse_number) {
+                Some(value) => Self::from(value),
+                None => return Err(format!("{other} is not a supported DNS record type")),
+            },
         };
         Ok(rt)
     }
 }
 
-impl RecordType {}
-
-impl TryFrom<u16> for RecordType {
-    type Error = anyhow::Error;
+/// The number of the generic `TYPEnnn` and `CLASSnnn` forms, which only has
+/// decimal digits (RFC 3597 5).
+pub(crate) fn parse_number(digits: &str) -> Option<u16> {
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
 
-    fn try_from(value: u16) -> Result<Self, Self::Error> {
-        let record_type = match value {
+impl From<u16> for RecordType {
+    fn from(value: u16) -> Self {
+        match value {
             1 => Self::A,
             2 => Self::NS,
             3 => Self::MD,
@@ -73,56 +107,65 @@ impl TryFrom<u16> for RecordType {
             15 => Self::MX,
             16 => Self::TXT,
             28 => Self::AAAA,
-            _ => anyhow::bail!("Invalid record type number {value:b}"),
-        };
-        Ok(record_type)
+            39 => Self::DNAME,
+            251 => Self::IXFR,
+            252 => Self::AXFR,
+            255 => Self::ANY,
+            other => Self::Unknown(other),
+        }
     }
 }
 
-impl TryFrom<u8> for RecordType {
-    type Error = anyhow::Error;
-
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        return Self::try_from(value as u16);
+impl From<RecordType> for u16 {
+    fn from(val: RecordType) -> Self {
+        match val {
+            RecordType::A => 1,
+            RecordType::NS => 2,
+            RecordType::MD => 3,
+            RecordType::MF => 4,
+            RecordType::CNAME => 5,
+            RecordType::SOA => 6,
+            RecordType::MB => 7,
+            RecordType::MG => 8,
+            RecordType::MR => 9,
+            RecordType::NULL => 10,
+            RecordType::WKS => 11,
+            RecordType::PTR => 12,
+            RecordType::HINFO => 13,
+            RecordType::MINFO => 14,
+            RecordType::MX => 15,
+            RecordType::TXT => 16,
+            RecordType::AAAA => 28,
+            RecordType::DNAME => 39,
+            RecordType::IXFR => 251,
+            RecordType::AXFR => 252,
+            RecordType::ANY => 255,
+            RecordType::Unknown(value) => value,
+        }
     }
 }
 
-impl<'a> From<RecordType> for &'a u16 {
-    fn from(val: RecordType) -> Self {
-        let type_num = match val {
-            RecordType::A => &1,
-            RecordType::NS => &2,
-            RecordType::MD => &3,
-            RecordType::MF => &4,
-            RecordType::CNAME => &5,
-            RecordType::SOA => &6,
-            RecordType::MB => &7,
-            RecordType::MG => &8,
-            RecordType::MR => &9,
-            RecordType::NULL => &10,
-            RecordType::WKS => &11,
-            RecordType::PTR => &12,
-            RecordType::HINFO => &13,
-            RecordType::MINFO => &14,
-            RecordType::MX => &15,
-            RecordType::TXT => &16,
-            RecordType::AAAA => &28,
-        };
-        type_num
+impl RecordType {
+    /// Whether this is a QTYPE, only valid in questions and not as the type
+    /// of a record (RFC 1035 3.2.3).
+    pub fn is_meta(self) -> bool {
+        matches!(self, Self::IXFR | Self::AXFR | Self::ANY)
     }
-}
 
-impl From<RecordType> for u16 {
-    fn from(val: RecordType) -> Self {
-        let type_num: &u16 = val.into();
-        *type_num
+    /// Fail when this is an unknown type standing for a known one, such as
+    /// `Unknown(1)` for A, which would not compare equal to what is parsed.
+    pub fn check(self) -> Result<(), DnsError> {
+        let value = u16::from(self);
+        if Self::from(value) != self {
+            return Err(DnsError::BadRecordType(value));
+        }
+        Ok(())
     }
-}
 
-impl<'a> RecordType {
-    pub fn as_bitslice(self) -> &'a BitSlice<u16, Msb0> {
-        let type_num: &'a u16 = self.into();
-        type_num.view_bits::<Msb0>()
+    pub fn as_bitvec(self) -> BitVec<usize, Msb0> {
+        let mut bv = BitVec::<usize, Msb0>::new();
+        bv.extend_from_bitslice(u16::from(self).view_bits::<Msb0>());
+        bv
     }
 }
 
@@ -132,7 +175,7 @@ mod tests_recordtype {
 
     #[test]
     fn test_from_u16() {
-        let record_type: RecordType = (1 as u16).try_into().unwrap();
+        let record_type: RecordType = 1u16.into();
         assert_eq!(record_type, RecordType::A);
     }
 
@@ -143,30 +186,67 @@ mod tests_recordtype {
     }
 
     #[test]
-    fn test_as_bitslice() {
-        let bitslice = RecordType::A.as_bitslice();
-        assert_eq!(bitslice, (1 as u16).view_bits::<Msb0>());
+    fn test_as_bitvec() {
+        let bv = RecordType::A.as_bitvec();
+        assert_eq!(bv, 1u16.view_bits::<Msb0>());
     }
 
     #[test]
     fn test_all_convert() {
-        for i in 1..16 {
-            let record_type: RecordType = i.try_into().unwrap();
+        for i in 0..=u16::MAX {
+            let record_type: RecordType = i.into();
             let n_record_type: u16 = record_type.into();
             assert_eq!(i, n_record_type);
 
-            let bitslice: &BitSlice<u16, Msb0> = record_type.as_bitslice();
-            assert_eq!(bitslice, (i as u16).view_bits::<Msb0>());
-            assert_eq!(bitslice.len(), 16); // two octets
+            let bv = record_type.as_bitvec();
+            assert_eq!(bv, i.view_bits::<Msb0>());
+            assert_eq!(bv.len(), 16); // two octets
+        }
+        assert_eq!(RecordType::from(39), RecordType::DNAME);
+        assert_eq!(RecordType::from(99), RecordType::Unknown(99));
+    }
+
+    #[test]
+    fn test_presentation() {
+        assert_eq!(RecordType::MX.to_string(), "MX");
+        assert_eq!(RecordType::Unknown(12345).to_string(), "TYPE12345");
+        for rt in [RecordType::AAAA, RecordType::Unknown(65535)] {
+            assert_eq!(rt.to_string().parse::<RecordType>().unwrap(), rt);
         }
+        // the generic form of a known type is that type
+        assert_eq!("type15".parse::<RecordType>().unwrap(), RecordType::MX);
+        assert!("TYPE".parse::<RecordType>().is_err());
+        assert!("TYPE65536".parse::<RecordType>().is_err());
+        assert!("TYPE-1".parse::<RecordType>().is_err());
+        assert!("TYPE+15".parse::<RecordType>().is_err());
+        assert_eq!(
+            "XX".parse::<RecordType>(),
+            Err("XX is not a supported DNS record type".to_string())
+        );
+    }
 
-        let i: u16 = 28;
-        let record_type: RecordType = i.try_into().unwrap();
-        let n_record_type: u16 = record_type.into();
-        assert_eq!(i, n_record_type);
+    #[test]
+    fn test_check() {
+        assert!(RecordType::AAAA.check().is_ok());
+        assert!(RecordType::Unknown(99).check().is_ok());
+        for value in [1, 28, 255] {
+            assert_eq!(
+                RecordType::Unknown(value).check(),
+                Err(DnsError::BadRecordType(value))
+            );
+        }
+    }
 
-        let bitslice: &BitSlice<u16, Msb0> = record_type.as_bitslice();
-        assert_eq!(bitslice, (i as u16).view_bits::<Msb0>());
-        assert_eq!(bitslice.len(), 16); // two octets
+    #[test]
+    fn test_meta() {
+        assert_eq!("any".parse::<RecordType>().unwrap(), RecordType::ANY);
+        assert_eq!("*".parse::<RecordType>().unwrap(), RecordType::ANY);
+        assert_eq!("AXFR".parse::<RecordType>().unwrap(), RecordType::AXFR);
+        assert_eq!("TYPE251".parse::<RecordType>().unwrap(), RecordType::IXFR);
+        assert_eq!(RecordType::ANY.to_string(), "ANY");
+        assert!(RecordType::AXFR.is_meta());
+        assert!(!RecordType::A.is_meta());
+        // other meta types, such as OPT, are kept opaque
+        assert!(!RecordType::Unknown(41).is_meta());
     }
 }